@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// Any deterministic simulation over finitely many states eventually loops: from step
+// `prefix_len` on, the state at step n is the same as at step n + `period`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    pub prefix_len: u64,
+    pub period: u64,
+}
+
+impl Cycle {
+    // Earliest step whose state is the same as the one at the given step.
+    pub fn reduce(&self, step: u64) -> u64 {
+        if step < self.prefix_len {
+            step
+        } else {
+            self.prefix_len + (step - self.prefix_len) % self.period
+        }
+    }
+
+    pub fn num_distinct_states(&self) -> u64 {
+        self.prefix_len + self.period
+    }
+}
+
+// Brent's algorithm, which only ever keeps two states around but calls `step` a few times
+// more per state than the hashing variant.
+pub fn brent<S: Clone + PartialEq>(start: &S, mut step: impl FnMut(&S) -> S) -> Cycle {
+    // Find the period by moving the tortoise to the hare at every power of two.
+    let mut power = 1;
+    let mut period = 1;
+    let mut tortoise = start.clone();
+    let mut hare = step(start);
+    while tortoise != hare {
+        if power == period {
+            tortoise = hare.clone();
+            power *= 2;
+            period = 0;
+        }
+        hare = step(&hare);
+        period += 1;
+    }
+
+    // With the hare `period` steps ahead, both meet at the first state of the loop.
+    let mut tortoise = start.clone();
+    let mut hare = start.clone();
+    for _ in 0..period {
+        hare = step(&hare);
+    }
+    let mut prefix_len = 0;
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        prefix_len += 1;
+    }
+    Cycle { prefix_len, period }
+}
+
+// States visited by a simulation up to the end of its first loop iteration.
+#[derive(Debug, Clone)]
+pub struct History<S> {
+    pub cycle: Cycle,
+    pub states: Vec<S>,
+}
+
+impl<S> History<S> {
+    pub fn state_at(&self, step: u64) -> &S {
+        &self.states[self.cycle.reduce(step) as usize]
+    }
+}
+
+// Records every state until one repeats. `step` is called exactly once per distinct state, in
+// order, so it may also accumulate results on the side.
+pub fn hashing<S: Clone + Eq + Hash>(start: S, mut step: impl FnMut(&S) -> S) -> History<S> {
+    let mut first_seen: HashMap<S, u64> = HashMap::new();
+    let mut states = Vec::new();
+    let mut state = start;
+
+    let prefix_len = loop {
+        if let Some(seen) = first_seen.get(&state) {
+            break *seen;
+        }
+        first_seen.insert(state.clone(), states.len() as u64);
+        let next = step(&state);
+        states.push(state);
+        state = next;
+    };
+    History {
+        cycle: Cycle {
+            prefix_len,
+            period: states.len() as u64 - prefix_len,
+        },
+        states,
+    }
+}
+
+// State after `num_steps` steps, with a number of calls to `step` linear in the prefix length
+// and period rather than in `num_steps`.
+pub fn fast_forward<S: Clone + PartialEq>(
+    start: &S,
+    mut step: impl FnMut(&S) -> S,
+    num_steps: u64,
+) -> S {
+    let cycle = brent(start, &mut step);
+    let mut state = start.clone();
+    for _ in 0..cycle.reduce(num_steps) {
+        state = step(&state);
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Maps x -> x * x + a mod m, which have all kinds of prefix lengths and periods.
+    fn quadratic_maps() -> impl Iterator<Item = (u64, u64)> {
+        (1..40).flat_map(|m| (0..m).map(move |a| (a, m)))
+    }
+
+    #[test]
+    fn brent_matches_hashing() {
+        for (a, m) in quadratic_maps() {
+            let step = |x: &u64| (x * x + a) % m;
+            for start in 0..m {
+                assert_eq!(
+                    brent(&start, step),
+                    hashing(start, step).cycle,
+                    "x * x + {} mod {} from {}",
+                    a,
+                    m,
+                    start
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fast_forward_matches_stepping() {
+        for (a, m) in quadratic_maps() {
+            let step = |x: &u64| (x * x + a) % m;
+            let mut state = 1 % m;
+            for num_steps in 0..3 * m {
+                assert_eq!(fast_forward(&(1 % m), step, num_steps), state);
+                state = step(&state);
+            }
+        }
+    }
+
+    #[test]
+    fn history_state_at_matches_stepping() {
+        let step = |x: &u64| (x * x + 1) % 1000;
+        let history = hashing(3, step);
+        let mut state = 3;
+        for num_steps in 0..200 {
+            assert_eq!(*history.state_at(num_steps), state);
+            state = step(&state);
+        }
+    }
+}
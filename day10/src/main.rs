@@ -1,17 +1,13 @@
-use core::num;
-use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
-use std::ops::{Deref, Range};
+use std::ops::Range;
 
 use chumsky::prelude::*;
-use chumsky::primitive::Container;
-use multiset::HashMultiSet;
-use petgraph::algo::dijkstra;
-use petgraph::data::Build;
-use petgraph::graph::NodeIndex;
-use petgraph::graph::{Graph, UnGraph};
+use itertools::Itertools;
+use petgraph::graph::Graph;
 use petgraph::visit::Dfs;
 
+mod render;
+
 type Coord = (i32, i32);
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -25,10 +21,60 @@ enum Pipe {
     DR,
 }
 
+impl Pipe {
+    // Offsets of the two tiles a pipe connects, the start tile connects nothing until its
+    // actual shape is inferred.
+    pub fn connections(&self) -> Vec<Coord> {
+        match self {
+            Pipe::S => vec![],
+            Pipe::V => vec![(-1, 0), (1, 0)],
+            Pipe::H => vec![(0, -1), (0, 1)],
+            Pipe::UL => vec![(1, 0), (0, 1)],
+            Pipe::DL => vec![(-1, 0), (0, 1)],
+            Pipe::UR => vec![(0, -1), (1, 0)],
+            Pipe::DR => vec![(0, -1), (-1, 0)],
+        }
+    }
+
+    pub fn from_connections(c1: Coord, c2: Coord) -> Option<Pipe> {
+        [Pipe::V, Pipe::H, Pipe::UL, Pipe::DL, Pipe::UR, Pipe::DR]
+            .into_iter()
+            .find(|p| {
+                let connections = p.connections();
+                connections.contains(&c1) && connections.contains(&c2)
+            })
+    }
+}
+
+#[derive(Debug)]
+enum MapError {
+    NoStart,
+    MultipleStarts(usize),
+    // The start tile must be connected to exactly two neighboring pipes.
+    StartConnections(Coord, usize),
+    // Following the pipes from the start leads to a tile that does not connect back.
+    NoClosedLoop(Coord),
+}
+
+impl std::fmt::Display for MapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MapError::NoStart => write!(f, "no start tile"),
+            MapError::MultipleStarts(n) => write!(f, "{} start tiles", n),
+            MapError::StartConnections(c, n) => {
+                write!(f, "start tile {:?} connects to {} pipes instead of 2", c, n)
+            }
+            MapError::NoClosedLoop(c) => write!(f, "loop is broken at {:?}", c),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Map {
-    pipe_loop: UnGraph<Coord, ()>,
-    start: (Coord, NodeIndex),
+    start: Coord,
+    start_pipe: Pipe,
+    // Loop coordinates in traversal order, starting from the start tile.
+    pipe_loop: Vec<Coord>,
 }
 
 fn parse_pipe_coords(
@@ -55,125 +101,141 @@ fn parse_pipe_coords(
             )
         })
         .padded_by(one_of(".\n").repeated());
-    nodes
-        .clone()
-        .repeated()
-        .map(|nodes| HashMap::from_iter(nodes))
+    nodes.clone().repeated().map(HashMap::from_iter)
 }
 
-fn map_from_pipes(node_coords: &HashMap<Coord, Pipe>) -> Map {
-    let all_edges = node_coords.iter().flat_map(move |(src_id @ (i, j), pipe)| {
-        match pipe {
-            Pipe::S => vec![], // Since we build an undirected graph, it will be picked up by the other nodes.
-            Pipe::V => vec![(-1, 0), (1, 0)],
-            Pipe::H => vec![(0, -1), (0, 1)],
-            Pipe::UL => vec![(1, 0), (0, 1)],
-            Pipe::DL => vec![(-1, 0), (0, 1)],
-            Pipe::UR => vec![(0, -1), (1, 0)],
-            Pipe::DR => vec![(0, -1), (-1, 0)],
+fn infer_start_pipe(pipes: &HashMap<Coord, Pipe>, start: Coord) -> Result<Pipe, MapError> {
+    let (i, j) = start;
+    let connected = Vec::from_iter([(-1, 0), (1, 0), (0, -1), (0, 1)].into_iter().filter(
+        |(oi, oj)| match pipes.get(&(i + oi, j + oj)) {
+            Some(pipe) => pipe.connections().contains(&(-oi, -oj)),
+            None => false,
+        },
+    ));
+    match connected[..] {
+        [c1, c2] => Ok(Pipe::from_connections(c1, c2).unwrap()),
+        _ => Err(MapError::StartConnections(start, connected.len())),
+    }
+}
+
+fn map_from_pipes(node_coords: &HashMap<Coord, Pipe>) -> Result<Map, MapError> {
+    let starts = Vec::from_iter(node_coords.iter().filter(|(_, p)| **p == Pipe::S));
+    let start = match starts[..] {
+        [] => return Err(MapError::NoStart),
+        [(start, _)] => *start,
+        _ => return Err(MapError::MultipleStarts(starts.len())),
+    };
+    let start_pipe = infer_start_pipe(node_coords, start)?;
+    let pipe_at = |c: Coord| {
+        if c == start {
+            Some(start_pipe)
+        } else {
+            node_coords.get(&c).copied()
         }
-        .into_iter()
-        .filter_map(move |(oi, oj)| {
-            let tgt_id = (i + oi, j + oj);
-            if node_coords.contains_key(&tgt_id) {
-                Some((*src_id, tgt_id))
-            } else {
-                None
-            }
-        })
-    });
-    let edges_with_counts =
-        HashMultiSet::from_iter(
-            all_edges.map(|(src, tgt)| if src < tgt { (src, tgt) } else { (tgt, src) }),
-        );
-    // We keep edges with multiplicity 2, except when they connect to the start, in which case
-    // we keep them always.
-    let start = node_coords
-        .into_iter()
-        .find_map(|(i, p)| if *p == Pipe::S { Some(i) } else { None })
-        .unwrap();
-    let edges_to_keep = edges_with_counts
-        .distinct_elements()
-        .filter_map(|e @ (src, tgt)| {
-            if src == start || tgt == start || edges_with_counts.count_of(e) == 2 {
-                Some(e)
-            } else {
-                None
-            }
-        });
-    let pipe_loop = RefCell::new(Graph::new_undirected());
-    let mut added_map: HashMap<(i32, i32), NodeIndex> = HashMap::new();
-
-    let mut get_coord_idx = |c| {
-        added_map.get(c).map(|v| *v).unwrap_or_else(|| {
-            let i1;
-            {
-                let mut pipe_loop = pipe_loop.borrow_mut();
-                i1 = pipe_loop.add_node(*c);
-            }
-            added_map.insert(*c, i1);
-            i1
-        })
     };
 
-    for (c1, c2) in edges_to_keep {
-        let i1 = get_coord_idx(c1);
-        let i2 = get_coord_idx(c2);
-        {
-            let mut pipe_loop = pipe_loop.borrow_mut();
-            pipe_loop.add_edge(i1, i2, ());
+    let mut pipe_loop = Vec::from([start]);
+    let mut prev = start;
+    let (oi, oj) = start_pipe.connections()[0];
+    let mut cur = (start.0 + oi, start.1 + oj);
+
+    while cur != start {
+        let (i, j) = cur;
+        let back = (prev.0 - i, prev.1 - j);
+        let connections = pipe_at(cur).map(|p| p.connections()).unwrap_or_default();
+        if !connections.contains(&back) {
+            return Err(MapError::NoClosedLoop(cur));
         }
+        let (oi, oj) = connections.into_iter().find(|c| *c != back).unwrap();
+        pipe_loop.push(cur);
+        prev = cur;
+        cur = (i + oi, j + oj);
     }
-
-    Map {
-        pipe_loop: pipe_loop.take(),
-        start: (*start, *(added_map.get(&start).unwrap())),
+    // Closing the loop requires the start to connect back to the last pipe.
+    let back = (prev.0 - start.0, prev.1 - start.1);
+    if !start_pipe.connections().contains(&back) {
+        return Err(MapError::NoClosedLoop(start));
     }
+
+    Ok(Map {
+        start,
+        start_pipe,
+        pipe_loop,
+    })
 }
 
-fn farthest_steps(map: &Map) -> i32 {
-    let (_, start) = map.start;
-    *(dijkstra(&map.pipe_loop, start.into(), None, |_| 1)
-        .values()
-        .into_iter()
-        .max()
-        .unwrap())
+fn farthest_steps(map: &Map) -> usize {
+    map.pipe_loop.len() / 2
 }
 
-fn num_contained(map: &Map, line_length: usize, num_lines: usize) -> i32 {
-    // Keep only the connected components of the start node by Dfs.
-    let mut cc_node_indices = HashSet::new();
-    let (start_coords @ (s_i, s_j), start_idx) = map.start;
-    let mut dfs = Dfs::new(&map.pipe_loop, start_idx);
+// Number of tiles enclosed by the loop from Pick's theorem: the shoelace formula gives the
+// area A of the polygon through the tile centers, and A = I + B / 2 - 1 with B loop tiles.
+fn num_enclosed_pick(pipe_loop: &[Coord]) -> i64 {
+    let double_area = pipe_loop
+        .iter()
+        .zip(pipe_loop.iter().cycle().skip(1))
+        .map(|((i1, j1), (i2, j2))| (*i1 as i64) * (*j2 as i64) - (*i2 as i64) * (*j1 as i64))
+        .sum::<i64>()
+        .abs();
+    (double_area - pipe_loop.len() as i64) / 2 + 1
+}
+
+// Enclosed tiles by scanning rows: crossing a loop tile connected to the tile above it
+// toggles between outside and inside.
+fn enclosed_tiles(pipe_loop: &[Coord]) -> HashSet<Coord> {
+    let loop_tiles: HashSet<Coord> = HashSet::from_iter(pipe_loop.iter().copied());
+    let mut connected_up = HashSet::new();
 
-    while let Some(node) = dfs.next(&map.pipe_loop) {
-        cc_node_indices.insert(node);
+    for (c1 @ (i1, j1), c2 @ (i2, j2)) in pipe_loop.iter().zip(pipe_loop.iter().cycle().skip(1)) {
+        if j1 == j2 && i1 - 1 == *i2 {
+            connected_up.insert(*c1);
+        } else if j1 == j2 && i2 - 1 == *i1 {
+            connected_up.insert(*c2);
+        }
     }
-    // Filter the nodes while doubling the coordinates to leave some gaps for a flood fill.
-    let bigger_loop = map.pipe_loop.filter_map(
-        |node, (i, j)| {
-            if cc_node_indices.contains(&node) {
-                Some((i * 2, j * 2))
-            } else {
-                None
+    let (min_i, max_i) = pipe_loop
+        .iter()
+        .map(|(i, _)| *i)
+        .minmax()
+        .into_option()
+        .unwrap();
+    let (min_j, max_j) = pipe_loop
+        .iter()
+        .map(|(_, j)| *j)
+        .minmax()
+        .into_option()
+        .unwrap();
+    let mut enclosed = HashSet::new();
+
+    for i in min_i..=max_i {
+        let mut inside = false;
+        for j in min_j..=max_j {
+            if connected_up.contains(&(i, j)) {
+                inside = !inside;
+            } else if inside && !loop_tiles.contains(&(i, j)) {
+                enclosed.insert((i, j));
             }
-        },
-        |_, e| Some(e),
-    );
+        }
+    }
+    enclosed
+}
 
+fn num_contained(map: &Map, line_length: usize, num_lines: usize) -> i32 {
+    // Double the coordinates to leave some gaps for a flood fill, and build up the loop
+    // coordinates by following edges.
     let mut cc_node_coords = HashSet::new();
 
-    // Build up coordinates by following edges.
-    for edge in bigger_loop.edge_indices() {
-        let (n1, n2) = bigger_loop.edge_endpoints(edge).unwrap();
-        let (i1, j1) = *(bigger_loop.node_weight(n1).unwrap());
-        let (i2, j2) = *(bigger_loop.node_weight(n2).unwrap());
+    for ((i1, j1), (i2, j2)) in map
+        .pipe_loop
+        .iter()
+        .zip(map.pipe_loop.iter().cycle().skip(1))
+    {
         let coords = if i1 == i2 {
-            Vec::from_iter((j1..=j2).map(|j| (i1, j)))
+            Vec::from_iter((2 * j1.min(j2)..=2 * j1.max(j2)).map(|j| (2 * i1, j)))
         } else {
-            Vec::from_iter((i1..=i2).map(|i| (i, j1)))
+            Vec::from_iter((2 * i1.min(i2)..=2 * i1.max(i2)).map(|i| (i, 2 * j1)))
         };
-        cc_node_coords.extend(coords.into_iter());
+        cc_node_coords.extend(coords);
     }
 
     let num_lines = num_lines as i32;
@@ -202,11 +264,8 @@ fn num_contained(map: &Map, line_length: usize, num_lines: usize) -> i32 {
     for ((i, j), src) in coord_to_node.iter() {
         for (oi, oj) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
             let tgt_coord = (i + oi, j + oj);
-            match coord_to_node.get(&tgt_coord) {
-                Some(tgt) => {
-                    empty_tile_graph.add_edge(*src, *tgt, ());
-                }
-                None => (),
+            if let Some(tgt) = coord_to_node.get(&tgt_coord) {
+                empty_tile_graph.add_edge(*src, *tgt, ());
             }
         }
     }
@@ -224,7 +283,7 @@ fn num_contained(map: &Map, line_length: usize, num_lines: usize) -> i32 {
         }
     }
 
-    let num_loop = bigger_loop.node_count() as i32;
+    let num_loop = map.pipe_loop.len() as i32;
     num_total - (num_outside_nodes + num_loop)
 }
 
@@ -234,11 +293,58 @@ fn main() {
     let num_lines = lines.clone().count();
     let line_length = lines.next().unwrap().len();
     let pipe_coords = parse_pipe_coords(line_length).parse(src.clone()).unwrap();
-    let map = map_from_pipes(&pipe_coords);
+    let map = match map_from_pipes(&pipe_coords) {
+        Ok(map) => map,
+        Err(err) => {
+            println!("Invalid map: {}", err);
+            return;
+        }
+    };
+
+    let num_enclosed = num_enclosed_pick(&map.pipe_loop);
+    let enclosed = enclosed_tiles(&map.pipe_loop);
+    assert_eq!(num_enclosed, enclosed.len() as i64);
+
+    // "svg [path]" writes the drawing to the file. Without a path the drawing is printed alone,
+    // so that the output is a valid SVG file.
+    let mode = std::env::args().nth(2);
+    let svg_path = std::env::args().nth(3);
+    if mode.as_deref() == Some("svg") && svg_path.is_none() {
+        print!(
+            "{}",
+            render::render_svg(&map, &enclosed, num_lines, line_length)
+        );
+        return;
+    }
 
-    println!("Question 1 answer is {}", farthest_steps(&map));
     println!(
-        "Question 2 answer is {}",
-        num_contained(&map, line_length, num_lines)
-    )
+        "Start tile {:?} is a {:?} pipe, loop has {} tiles",
+        map.start,
+        map.start_pipe,
+        map.pipe_loop.len()
+    );
+    println!("Question 1 answer is {}", farthest_steps(&map));
+    println!("Question 2 answer is {}", num_enclosed);
+
+    match mode.as_deref() {
+        // The flood fill on the doubled grid is much slower, so it only runs on demand.
+        Some("check") => {
+            assert_eq!(
+                num_enclosed,
+                num_contained(&map, line_length, num_lines) as i64
+            );
+            println!("Flood fill agrees with the loop based count");
+        }
+        Some("render") => print!(
+            "{}",
+            render::render_text(&pipe_coords, &map, &enclosed, num_lines, line_length)
+        ),
+        Some("svg") => {
+            let path = svg_path.unwrap();
+            let svg = render::render_svg(&map, &enclosed, num_lines, line_length);
+            std::fs::write(&path, svg).unwrap();
+            println!("Wrote loop drawing to {}", path);
+        }
+        _ => (),
+    }
 }
@@ -0,0 +1,105 @@
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+
+use crate::{Coord, Map, Pipe};
+
+const DIM_INSIDE: &str = "\x1b[2;32m";
+const DIM_OUTSIDE: &str = "\x1b[2;34m";
+const LOOP: &str = "\x1b[1m";
+const START: &str = "\x1b[1;33m";
+const RESET: &str = "\x1b[0m";
+
+fn box_char(pipe: Pipe) -> char {
+    match pipe {
+        Pipe::S => 'S',
+        Pipe::H => '─',
+        Pipe::V => '│',
+        Pipe::UL => '┌',
+        Pipe::UR => '┐',
+        Pipe::DL => '└',
+        Pipe::DR => '┘',
+    }
+}
+
+// Redraws the map with box drawing characters. The loop is drawn in bold with the start tile
+// shown as its inferred pipe, every other tile is dimmed and colored green when enclosed by
+// the loop or blue when outside of it.
+pub fn render_text(
+    pipes: &HashMap<Coord, Pipe>,
+    map: &Map,
+    enclosed: &HashSet<Coord>,
+    num_lines: usize,
+    line_length: usize,
+) -> String {
+    let loop_tiles: HashSet<Coord> = HashSet::from_iter(map.pipe_loop.iter().copied());
+    let mut rendered = String::new();
+
+    for i in 0..num_lines as i32 {
+        for j in 0..line_length as i32 {
+            let c = (i, j);
+            let (color, tile) = if c == map.start {
+                (START, box_char(map.start_pipe))
+            } else if loop_tiles.contains(&c) {
+                (LOOP, box_char(pipes[&c]))
+            } else {
+                let color = if enclosed.contains(&c) {
+                    DIM_INSIDE
+                } else {
+                    DIM_OUTSIDE
+                };
+                (color, pipes.get(&c).map(|p| box_char(*p)).unwrap_or('·'))
+            };
+            rendered.push_str(&format!("{}{}{}", color, tile, RESET));
+        }
+        rendered.push('\n');
+    }
+    rendered
+}
+
+// SVG drawing of the loop through the tile centers, with enclosed tiles filled.
+pub fn render_svg(
+    map: &Map,
+    enclosed: &HashSet<Coord>,
+    num_lines: usize,
+    line_length: usize,
+) -> String {
+    let tile_size = 10;
+    let center = |x: i32| x * tile_size + tile_size / 2;
+    let points = map
+        .pipe_loop
+        .iter()
+        .map(|(i, j)| format!("{},{}", center(*j), center(*i)))
+        .join(" ");
+    let tiles = enclosed
+        .iter()
+        .sorted()
+        .map(|(i, j)| {
+            format!(
+                "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"seagreen\"/>\n",
+                j * tile_size,
+                i * tile_size,
+                tile_size,
+                tile_size
+            )
+        })
+        .join("");
+
+    format!(
+        concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\">\n",
+            "  <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n",
+            "{}",
+            "  <polygon points=\"{}\" fill=\"none\" stroke=\"black\" stroke-width=\"2\"/>\n",
+            "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"goldenrod\"/>\n",
+            "</svg>\n"
+        ),
+        line_length as i32 * tile_size,
+        num_lines as i32 * tile_size,
+        tiles,
+        points,
+        center(map.start.1),
+        center(map.start.0),
+        tile_size / 3
+    )
+}
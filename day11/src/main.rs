@@ -1,6 +1,10 @@
 use chumsky::prelude::*;
-use itertools::Itertools;
-use std::{collections::HashSet, ops::Range};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::ops::Range;
+
+mod query;
+
+use query::Metric;
 
 type Scalar = i64;
 
@@ -20,47 +24,138 @@ fn parse_image(line_length: usize) -> impl Parser<char, Image, Error = Simple<ch
         .at_least(1)
 }
 
-fn empty_dim(image: &Image, dim: usize) -> Vec<Scalar> {
-    let all_is: HashSet<Scalar> = HashSet::from_iter(image.iter().map(|t| t[dim]));
-    let max_i = all_is.iter().max().unwrap();
-    (0..*max_i)
-        .into_iter()
-        .filter(move |i| !all_is.contains(i))
-        .collect()
+// Sorted distinct coordinates of the galaxies along a dimension.
+fn occupied_dim(image: &Image, dim: usize) -> Vec<Scalar> {
+    let mut occupied = Vec::from_iter(image.iter().map(|g| g[dim]));
+    occupied.sort_unstable();
+    occupied.dedup();
+    occupied
 }
 
-fn expand_image(image: &Image, scale: Scalar) -> Image {
-    let empty_rows = empty_dim(image, 0);
-    let empty_cols = empty_dim(image, 1);
+// Empty lines along a dimension of an image with the given extent. Lines after the last galaxy
+// are as empty as those in between and are listed too.
+fn empty_dim(image: &Image, dim: usize, extent: Scalar) -> Vec<Scalar> {
+    let occupied = occupied_dim(image, dim);
+    Vec::from_iter((0..extent).filter(|i| occupied.binary_search(i).is_err()))
+}
+
+// Expands empty rows (dim 0) and columns (dim 1) by their own scale, each empty line adding
+// `scales[dim]` lines to the image. The number of empty lines before a galaxy is its
+// coordinate minus the number of occupied lines before it.
+fn expand_image(image: &Image, scales: [Scalar; 2]) -> Image {
+    let occupied_lines = [occupied_dim(image, 0), occupied_dim(image, 1)];
     image
-        .into_iter()
-        .map(|[i, j]| {
-            let num_before_i = empty_rows.iter().filter(|i_| *i_ < i).count() as Scalar;
-            let num_before_j = empty_cols.iter().filter(|j_| *j_ < j).count() as Scalar;
-            [i + num_before_i * scale, j + num_before_j * scale]
+        .iter()
+        .map(|galaxy| {
+            [0, 1].map(|dim| {
+                let num_occupied = occupied_lines[dim].partition_point(|o| *o < galaxy[dim]);
+                galaxy[dim] + (galaxy[dim] - num_occupied as Scalar) * scales[dim]
+            })
         })
         .collect()
 }
 
-fn all_pair_distance<'a>(image: &'a Image) -> impl Iterator<Item = Scalar> + 'a {
-    image.iter().combinations(2).map(|points| {
-        let [i1, j1] = points[0];
-        let [i2, j2] = points[1];
-        (i2 - i1).abs() + (j2 - j1).abs()
-    })
+// The Manhattan distance is a sum over axes, and along an axis the sorted k-th coordinate is
+// at distance v_k - v_l of the l < k ones, so every pair is counted through prefix sums.
+fn sum_pair_distances(image: &Image) -> i128 {
+    [0, 1]
+        .into_iter()
+        .map(|dim| {
+            let mut values = Vec::from_iter(image.iter().map(|g| g[dim] as i128));
+            values.sort_unstable();
+            let mut prefix_sum = 0_i128;
+            let mut total = 0_i128;
+            for (k, v) in values.into_iter().enumerate() {
+                total += v * k as i128 - prefix_sum;
+                prefix_sum += v;
+            }
+            total
+        })
+        .sum()
+}
+
+fn expanded_extents(image: &Image, extents: [Scalar; 2], scales: [Scalar; 2]) -> [Scalar; 2] {
+    [0, 1]
+        .map(|dim| extents[dim] + empty_dim(image, dim, extents[dim]).len() as Scalar * scales[dim])
+}
+
+fn run_query(image: &Image, extents: [Scalar; 2], args: &[String]) {
+    let scales = [1, 1];
+    let [num_rows, num_cols] = expanded_extents(image, extents, scales);
+    println!("Expanded universe is {} x {}", num_rows, num_cols);
+    let expanded = expand_image(image, scales);
+    let metric = |i: usize| -> Metric {
+        args.get(i)
+            .map(|m| m.parse().unwrap())
+            .unwrap_or(Metric::Manhattan)
+    };
+
+    match args[0].as_str() {
+        "distance" => {
+            let n1: usize = args[1].parse().unwrap();
+            let n2: usize = args[2].parse().unwrap();
+            match query::galaxy_distance(&expanded, n1, n2, metric(3)) {
+                Some(d) => println!("Galaxies {} and {} are {} apart", n1, n2, d),
+                None => println!("There are only {} galaxies", expanded.len()),
+            }
+        }
+        "nearest" => {
+            for (k, nearest) in query::nearest_neighbors(&expanded, metric(1))
+                .into_iter()
+                .enumerate()
+            {
+                match nearest {
+                    Some((n, d)) => println!("Galaxy {} is nearest to {} at {}", k + 1, n, d),
+                    None => println!("Galaxy {} is alone", k + 1),
+                }
+            }
+        }
+        "closest" => {
+            let k: usize = args[1].parse().unwrap();
+            for (n1, n2, d) in query::closest_pairs(&expanded, k, metric(2)) {
+                println!("Galaxies {} and {} are {} apart", n1, n2, d);
+            }
+        }
+        query => println!("Unknown query {}", query),
+    }
+}
+
+fn synthetic_image(num_galaxies: usize, size: Scalar, seed: u64) -> Image {
+    let mut rng = StdRng::seed_from_u64(seed);
+    Vec::from_iter((0..num_galaxies).map(|_| [rng.gen_range(0..size), rng.gen_range(0..size)]))
 }
 
 fn main() {
-    let src = std::fs::read_to_string(std::env::args().nth(1).unwrap()).unwrap();
-    let line_length = src.lines().next().unwrap().len();
-    let image = parse_image(line_length).parse(src.clone()).unwrap();
+    // "synthetic <num galaxies> <size>" runs on random galaxies instead of an input file.
+    let args = Vec::from_iter(std::env::args());
+    let (image, extents, query_args) = if args[1] == "synthetic" {
+        let num_galaxies: usize = args[2].parse().unwrap();
+        let size: Scalar = args[3].parse().unwrap();
+        (
+            synthetic_image(num_galaxies, size, 0),
+            [size, size],
+            &args[4..],
+        )
+    } else {
+        let src = std::fs::read_to_string(&args[1]).unwrap();
+        let line_length = src.lines().next().unwrap().len();
+        let extents = [src.lines().count() as Scalar, line_length as Scalar];
+        let image = parse_image(line_length).parse(src.clone()).unwrap();
+        (image, extents, &args[2..])
+    };
+    // Extra arguments query the universe expanded as in question 1:
+    // "distance <n1> <n2> [metric]", "nearest [metric]" or "closest <k> [metric]".
+    if !query_args.is_empty() {
+        run_query(&image, extents, query_args);
+        return;
+    }
 
     println!(
         "Question 1 answer is: {}",
-        all_pair_distance(&expand_image(&image, 1)).sum::<Scalar>()
+        sum_pair_distances(&expand_image(&image, [1, 1]))
     );
     println!(
         "Question 2 answer is: {}",
-        all_pair_distance(&expand_image(&image, 999999)).sum::<Scalar>()
+        sum_pair_distances(&expand_image(&image, [999999, 999999]))
     );
 }
@@ -0,0 +1,124 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::str::FromStr;
+
+use crate::{Galaxy, Image};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Metric {
+    Manhattan,
+    Chebyshev,
+    Euclidean,
+}
+
+impl Metric {
+    pub fn distance(&self, g1: &Galaxy, g2: &Galaxy) -> f64 {
+        let di = (g1[0] - g2[0]).abs() as f64;
+        let dj = (g1[1] - g2[1]).abs() as f64;
+        match self {
+            Metric::Manhattan => di + dj,
+            Metric::Chebyshev => di.max(dj),
+            Metric::Euclidean => di.hypot(dj),
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Metric, String> {
+        match s {
+            "manhattan" => Ok(Metric::Manhattan),
+            "chebyshev" => Ok(Metric::Chebyshev),
+            "euclidean" => Ok(Metric::Euclidean),
+            _ => Err(format!("unknown metric {}", s)),
+        }
+    }
+}
+
+// Galaxies are numbered from 1 in reading order, as in the puzzle statement.
+pub fn galaxy_distance(image: &Image, n1: usize, n2: usize, metric: Metric) -> Option<f64> {
+    let g1 = image.get(n1.checked_sub(1)?)?;
+    let g2 = image.get(n2.checked_sub(1)?)?;
+    Some(metric.distance(g1, g2))
+}
+
+// Galaxy indices sorted by row. The row difference is a lower bound of the distance under
+// all metrics, so scans along this order stop as soon as it exceeds the best distance.
+fn by_row(image: &Image) -> Vec<usize> {
+    let mut order = Vec::from_iter(0..image.len());
+    order.sort_by_key(|k| image[*k]);
+    order
+}
+
+fn row_gap(image: &Image, k1: usize, k2: usize) -> f64 {
+    (image[k1][0] - image[k2][0]).abs() as f64
+}
+
+// Number and distance of the nearest other galaxy for every galaxy, by number.
+pub fn nearest_neighbors(image: &Image, metric: Metric) -> Vec<Option<(usize, f64)>> {
+    let order = by_row(image);
+    let mut nearest: Vec<Option<(usize, f64)>> = vec![None; image.len()];
+
+    for (pos, k) in order.iter().enumerate() {
+        let mut best: Option<(usize, f64)> = None;
+        let mut scan = |others: &mut dyn Iterator<Item = &usize>| {
+            for other in others {
+                if best.is_some_and(|(_, d)| row_gap(image, *k, *other) > d) {
+                    break;
+                }
+                let d = metric.distance(&image[*k], &image[*other]);
+                if best.is_none_or(|(_, best_d)| d < best_d) {
+                    best = Some((other + 1, d));
+                }
+            }
+        };
+        scan(&mut order[..pos].iter().rev());
+        scan(&mut order[pos + 1..].iter());
+        nearest[*k] = best;
+    }
+    nearest
+}
+
+#[derive(Debug, PartialEq)]
+struct Pair(f64, usize, usize);
+
+impl Eq for Pair {}
+
+impl PartialOrd for Pair {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pair {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .total_cmp(&other.0)
+            .then((self.1, self.2).cmp(&(other.1, other.2)))
+    }
+}
+
+// The k closest pairs of galaxies as (number, number, distance), closest first.
+pub fn closest_pairs(image: &Image, k: usize, metric: Metric) -> Vec<(usize, usize, f64)> {
+    let order = by_row(image);
+    // Max heap of the k best pairs so far, its top is the one to evict.
+    let mut best: BinaryHeap<Pair> = BinaryHeap::new();
+
+    for (pos, g1) in order.iter().enumerate() {
+        for g2 in order[pos + 1..].iter() {
+            if best.len() == k && best.peek().is_some_and(|p| row_gap(image, *g1, *g2) > p.0) {
+                break;
+            }
+            let (n1, n2) = ((*g1).min(*g2) + 1, (*g1).max(*g2) + 1);
+            best.push(Pair(metric.distance(&image[*g1], &image[*g2]), n1, n2));
+            if best.len() > k {
+                best.pop();
+            }
+        }
+    }
+    best.into_sorted_vec()
+        .into_iter()
+        .map(|Pair(d, n1, n2)| (n1, n2, d))
+        .collect()
+}
@@ -1,40 +1,32 @@
-use bitfield::BitRange;
 use chumsky::prelude::*;
-use itertools::{Either, Itertools};
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::ops::Range;
+use itertools::Itertools;
+use num::bigint::RandBigInt;
+use num::{BigUint, One, Zero};
+use rand::Rng;
+use rayon::prelude::*;
 
-// Represent an arrangement as a bit field
-// where 1 is # and 0 is from the least significant
-// bits onward.
+mod nonogram;
 
-type Scalar = u128;
+// Known tiles of a record, None for unknown ones.
+type Tiles = Vec<Option<Tile>>;
 
 #[derive(Debug, Clone)]
 struct SpringRecord {
-    damaged: Scalar,
-    operational: Scalar,
+    tiles: Tiles,
     sizes: Vec<usize>,
-    total_size: usize,
 }
 
-#[derive(PartialEq, PartialOrd, Ord, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, PartialOrd, Ord, Eq, Clone, Copy)]
 enum Tile {
     D,
     O,
 }
 
 fn parse_spring_records() -> impl Parser<char, Vec<SpringRecord>, Error = Simple<char>> {
-    let tile = one_of("#.?").map_with_span(|c: char, r: Range<usize>| {
-        (
-            match c {
-                '#' => Some(Tile::D),
-                '.' => Some(Tile::O),
-                _ => None,
-            },
-            r.start,
-        )
+    let tile = one_of("#.?").map(|c: char| match c {
+        '#' => Some(Tile::D),
+        '.' => Some(Tile::O),
+        _ => None,
     });
     let sizes = text::int(10)
         .map(|s: String| s.parse().unwrap())
@@ -42,241 +34,334 @@ fn parse_spring_records() -> impl Parser<char, Vec<SpringRecord>, Error = Simple
     let spring_record = tile
         .repeated()
         .at_least(1)
-        .map_with_span(|t, s: Range<usize>| (t, s))
         .padded()
         .then(sizes)
-        .map_with_span(|((tiles, tiles_span), sizes), span: Range<usize>| {
-            let (damaged, operational): (Vec<Scalar>, Vec<Scalar>) = tiles
-                .iter()
-                .filter_map(|(t, i)| match t {
-                    Some(t_) => Some((t_, i)),
-                    None => None,
-                })
-                .partition_map(|(t, i)| {
-                    let tile_bits = (2 as Scalar).pow((i - span.start) as u32);
-                    match t {
-                        Tile::D => Either::Left(tile_bits),
-                        Tile::O => Either::Right(tile_bits),
-                    }
-                });
-            SpringRecord {
-                damaged: damaged.into_iter().fold(0, |i1, i2| i1 | i2),
-                operational: operational.into_iter().fold(0, |i1, i2| i1 | i2),
-                sizes: sizes,
-                total_size: (tiles_span.end - tiles_span.start),
-            }
-        });
+        .map(|(tiles, sizes)| SpringRecord { tiles, sizes });
     spring_record.separated_by(text::newline())
 }
 
-fn group_sizes_match(i: Scalar, record: &SpringRecord) -> bool {
-    let mut i = i;
-    let mut actual_group_size = Vec::new();
-
-    while i != 0 {
-        i >>= i.trailing_zeros();
-        let group_size = i.trailing_ones();
-        actual_group_size.push(group_size as usize);
-        i >>= i.trailing_ones();
-    }
-    actual_group_size == record.sizes
+fn group_sizes(tiles: &[Tile]) -> Vec<usize> {
+    tiles
+        .iter()
+        .group_by(|t| **t)
+        .into_iter()
+        .filter_map(|(t, group)| match t {
+            Tile::D => Some(group.count()),
+            Tile::O => None,
+        })
+        .collect()
 }
 
-fn arrangements<'a>(record: &'a SpringRecord) -> impl Iterator<Item = Scalar> + 'a {
-    // Taking rough lower and upper bounds based on the sizes
-    // of the groups and the total size.
-    let num_damaged = record.sizes.iter().sum();
-    let min_val = (0..num_damaged)
-        .map(|i| (2 as Scalar).pow(i as u32))
-        .fold(0, |i1, i2| i1 | i2);
-    let max_val = ((record.total_size - num_damaged) as Scalar..(record.total_size as Scalar))
-        .map(|i| (2 as Scalar).pow(i as u32))
-        .fold(0, |i1, i2| i1 | i2);
-    (min_val..max_val).filter(move |i| {
-        let is_correct = i & record.damaged == record.damaged
-            && !i & record.operational == record.operational
-            && i.count_ones() == num_damaged as u32
-            && group_sizes_match(*i, record);
-        is_correct
-    })
+// Brute force over every assignment of the unknown tiles, only usable on small records.
+fn brute_force_arrangements(record: &SpringRecord) -> impl Iterator<Item = Vec<Tile>> + '_ {
+    record
+        .tiles
+        .iter()
+        .map(|t| match t {
+            Some(t) => vec![*t],
+            None => vec![Tile::D, Tile::O],
+        })
+        .multi_cartesian_product()
+        .filter(|tiles| group_sizes(tiles) == record.sizes)
 }
 
-fn expand(bitfield: Scalar, size: usize) -> Scalar {
-    let mut out = bitfield;
-
-    for _ in 1..5 {
-        out |= out << size + 1;
+// Record repeated `factor` times, the copies being separated by an unknown tile.
+fn unfold(record: &SpringRecord, factor: usize) -> SpringRecord {
+    SpringRecord {
+        tiles: vec![record.tiles.clone(); factor].join(&None),
+        sizes: record.sizes.repeat(factor),
     }
-    out
 }
 
-fn num_arrangements_all_unk(
+// Number of operational tiles before each position, to check that a group fits in O(1).
+fn operational_before(record: &SpringRecord) -> Vec<usize> {
+    Vec::from_iter([0].into_iter().chain(record.tiles.iter().scan(0, |acc, t| {
+        *acc += (*t == Some(Tile::O)) as usize;
+        Some(*acc)
+    })))
+}
+
+// Position following group g when it is placed at p, including the operational tile after it,
+// or None when the group does not fit there.
+fn place_group(
     record: &SpringRecord,
-    cur_group_id: usize,
-    prev_group_loc: Option<usize>,
-) -> u64 {
-    // If all remains is ??? we don't need to make any checks or build the actual solution.
-    if cur_group_id >= record.sizes.len() {
-        1
+    operational_before: &[usize],
+    g: usize,
+    p: usize,
+) -> Option<usize> {
+    let num_tiles = record.tiles.len();
+    let end = p + record.sizes[g];
+    if end <= num_tiles
+        && operational_before[end] == operational_before[p]
+        && record.tiles.get(end) != Some(&Some(Tile::D))
+    {
+        Some((end + 1).min(num_tiles))
     } else {
-        let group_sizes_after = &record.sizes[cur_group_id..];
-        let start_loc = prev_group_loc
-            .map(|i| i + record.sizes[cur_group_id - 1] + 1)
-            .unwrap_or(0);
-        let end_loc = record.total_size
-            - (group_sizes_after.iter().sum::<usize>() + (group_sizes_after.iter().count() - 1))
-            + 1;
-        let process_loc = |cur_group_loc: usize| -> u64 {
-            num_arrangements_all_unk(record, cur_group_id + 1, Some(cur_group_loc))
-        };
-        (start_loc..end_loc).into_iter().map(process_loc).sum()
+        None
     }
 }
 
-fn num_arrangements_fast(
-    record: &SpringRecord,
-    cur_group_id: usize,
-    prev_group_loc: Option<usize>,
-    cur_solution: Scalar,
-    cache: &RefCell<HashMap<(Scalar, Scalar, usize, Vec<usize>), u64>>,
-) -> u64 {
-    // Start by checking if the solution up to now is compatible with known
-    // damaged/operational. Prune if that's not the case.
-    let start_loc = prev_group_loc
-        .map(|i| i + record.sizes[cur_group_id - 1] + 1)
-        .unwrap_or(0);
-    let do_slice = |bitfield: Scalar| -> Scalar {
-        if start_loc == 0 {
-            0
-        } else {
-            bitfield.bit_range(start_loc - 1, 0)
+// Dynamic programming table where table[g][p] is the number of ways to place the groups from
+// g onward in the tiles from position p onward.
+fn count_table(record: &SpringRecord) -> Vec<Vec<BigUint>> {
+    let num_tiles = record.tiles.len();
+    let num_groups = record.sizes.len();
+    let operational_before = operational_before(record);
+    let mut table = vec![vec![BigUint::zero(); num_tiles + 1]; num_groups + 1];
+
+    // Once all groups are placed, the remaining tiles must all be operational.
+    table[num_groups][num_tiles] = BigUint::one();
+    for p in (0..num_tiles).rev() {
+        if record.tiles[p] != Some(Tile::D) {
+            table[num_groups][p] = table[num_groups][p + 1].clone();
         }
-    };
-    let slice_sol = do_slice(cur_solution);
-    let slice_dmg = do_slice(record.damaged);
-    let slice_op = do_slice(record.operational);
+    }
+    for g in (0..num_groups).rev() {
+        for p in (0..num_tiles).rev() {
+            let mut count = BigUint::zero();
+            if record.tiles[p] != Some(Tile::D) {
+                count += &table[g][p + 1];
+            }
+            if let Some(next_p) = place_group(record, &operational_before, g, p) {
+                count += &table[g + 1][next_p];
+            }
+            table[g][p] = count;
+        }
+    }
+    table
+}
 
-    // Check whether we don't have any constraint ahead, if so switch to the
-    // faster, unknown only solution.
-    let remaining_dmg: Scalar = record
-        .damaged
-        .bit_range(Scalar::BITS as usize - 1, start_loc);
-    let remaining_op: Scalar = record
-        .operational
-        .bit_range(Scalar::BITS as usize - 1, start_loc);
+// Same recurrence as the count table, keeping only the rows of the current and next groups so
+// that memory stays linear in the record length.
+fn num_arrangements(record: &SpringRecord) -> BigUint {
+    let num_tiles = record.tiles.len();
+    let operational_before = operational_before(record);
+    let mut next_row = vec![BigUint::zero(); num_tiles + 1];
 
-    if slice_sol & slice_dmg != slice_dmg || !slice_sol & slice_op != slice_op {
-        0
-    } else if cur_group_id >= record.sizes.len() {
-        if cur_solution & record.damaged == record.damaged
-            && !cur_solution & record.operational == record.operational
-        {
-            // Finished and passed checks, the solution is correct.
-            1
-        } else {
-            0
+    next_row[num_tiles] = BigUint::one();
+    for p in (0..num_tiles).rev() {
+        if record.tiles[p] != Some(Tile::D) {
+            next_row[p] = next_row[p + 1].clone();
         }
-    } else {
-        let cache_key = (
-            remaining_dmg,
-            remaining_op,
-            record.total_size - start_loc,
-            record.sizes[cur_group_id..]
-                .into_iter()
-                .map(|t| *t)
-                .collect(),
-        );
-        let cache_val;
-        {
-            cache_val = cache.borrow().get(&cache_key).map(|t| *t);
+    }
+    for g in (0..record.sizes.len()).rev() {
+        let mut row = vec![BigUint::zero(); num_tiles + 1];
+        for p in (0..num_tiles).rev() {
+            if record.tiles[p] != Some(Tile::D) {
+                row[p] = row[p + 1].clone();
+            }
+            if let Some(next_p) = place_group(record, &operational_before, g, p) {
+                row[p] += &next_row[next_p];
+            }
         }
-        match cache_val {
-            None => {
-                let out = if remaining_dmg == 0 && remaining_op == 0 {
-                    num_arrangements_all_unk(record, cur_group_id, prev_group_loc)
-                } else {
-                    let group_sizes_after = &record.sizes[cur_group_id..];
-                    let end_loc = record.total_size
-                        - (group_sizes_after.iter().sum::<usize>()
-                            + (group_sizes_after.iter().count() - 1))
-                        + 1;
-                    let process_loc = |cur_group_loc: usize| -> u64 {
-                        let group_mask = (0_u32..record.sizes[cur_group_id] as u32)
-                            .map(|i| 2_u128.pow(i))
-                            .fold(0, |i1, i2| i1 | i2)
-                            << cur_group_loc;
-                        let new_solution = cur_solution | group_mask;
-                        num_arrangements_fast(
-                            record,
-                            cur_group_id + 1,
-                            Some(cur_group_loc),
-                            new_solution,
-                            cache,
-                        )
-                    };
-                    (start_loc..end_loc).into_iter().map(process_loc).sum()
-                };
-                {
-                    let mut cache = cache.borrow_mut();
-                    cache.insert(cache_key, out);
+        next_row = row;
+    }
+    next_row.swap_remove(0)
+}
+
+fn render(tiles: &[Tile]) -> String {
+    String::from_iter(tiles.iter().map(|t| match t {
+        Tile::D => '#',
+        Tile::O => '.',
+    }))
+}
+
+// Appends group g placed at the end of the tiles, followed by an operational tile unless the
+// group ends the record.
+fn push_group(tiles: &mut Vec<Tile>, size: usize, next_p: usize) {
+    let start = tiles.len();
+    tiles.extend(std::iter::repeat_n(Tile::D, size));
+    tiles.resize(next_p.max(start + size), Tile::O);
+}
+
+// Lazy enumeration of the valid arrangements in lexicographic order of their rendering, which
+// puts # before . at every position. The count table prunes every branch with no solution.
+struct Arrangements<'a> {
+    record: &'a SpringRecord,
+    table: Vec<Vec<BigUint>>,
+    operational_before: Vec<usize>,
+    tiles: Vec<Tile>,
+    // (group, position, next choice) where the choice is 0 to place the group, 1 to skip the
+    // tile and 2 when both were explored.
+    stack: Vec<(usize, usize, u8)>,
+}
+
+fn arrangements(record: &SpringRecord) -> Arrangements<'_> {
+    let table = count_table(record);
+    let stack = if table[0][0].is_zero() {
+        Vec::new()
+    } else {
+        Vec::from([(0, 0, 0)])
+    };
+    Arrangements {
+        record,
+        table,
+        operational_before: operational_before(record),
+        tiles: Vec::new(),
+        stack,
+    }
+}
+
+impl Iterator for Arrangements<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let num_tiles = self.record.tiles.len();
+        let num_groups = self.record.sizes.len();
+
+        loop {
+            let (g, p, choice) = self.stack.last_mut()?;
+            let (g, p) = (*g, *p);
+            self.tiles.truncate(p);
+            if g == num_groups {
+                self.tiles.resize(num_tiles, Tile::O);
+                self.stack.pop();
+                return Some(render(&self.tiles));
+            }
+            *choice += 1;
+            match *choice {
+                1 => {
+                    if let Some(next_p) = place_group(self.record, &self.operational_before, g, p) {
+                        if !self.table[g + 1][next_p].is_zero() {
+                            push_group(&mut self.tiles, self.record.sizes[g], next_p);
+                            self.stack.push((g + 1, next_p, 0));
+                        }
+                    }
+                }
+                2 => {
+                    if self.record.tiles[p] != Some(Tile::D) && !self.table[g][p + 1].is_zero() {
+                        self.tiles.push(Tile::O);
+                        self.stack.push((g, p + 1, 0));
+                    }
+                }
+                _ => {
+                    self.stack.pop();
                 }
-                out
             }
-            Some(out) => out,
         }
     }
 }
 
+// Draws arrangements uniformly: at every tile the group is placed or the tile skipped with
+// probabilities proportional to the number of arrangements each choice leads to.
+fn sample_arrangements(
+    record: &SpringRecord,
+    num_samples: usize,
+    rng: &mut impl Rng,
+) -> Vec<String> {
+    let table = count_table(record);
+    if table[0][0].is_zero() {
+        return Vec::new();
+    }
+    let operational_before = operational_before(record);
+    let num_groups = record.sizes.len();
+
+    (0..num_samples)
+        .map(|_| {
+            let mut tiles = Vec::new();
+            let (mut g, mut p) = (0, 0);
+            while g < num_groups {
+                let place_count = match place_group(record, &operational_before, g, p) {
+                    Some(next_p) => table[g + 1][next_p].clone(),
+                    None => BigUint::zero(),
+                };
+                if rng.gen_biguint_below(&table[g][p]) < place_count {
+                    let next_p = place_group(record, &operational_before, g, p).unwrap();
+                    push_group(&mut tiles, record.sizes[g], next_p);
+                    g += 1;
+                    p = next_p;
+                } else {
+                    tiles.push(Tile::O);
+                    p += 1;
+                }
+            }
+            tiles.resize(record.tiles.len(), Tile::O);
+            render(&tiles)
+        })
+        .collect()
+}
+
 fn main() {
-    let src = std::fs::read_to_string(std::env::args().nth(1).unwrap()).unwrap();
-    let records = parse_spring_records().parse(src.clone()).unwrap();
-    let cache = RefCell::new(HashMap::new());
-    let output_fast = records
-        .iter()
-        .map(|r| num_arrangements_fast(r, 0, None, 0, &cache));
-    let output_slow = records.iter().map(|r| arrangements(r).count() as u64);
+    // Flags can appear anywhere, the remaining arguments are positional.
+    let verify = std::env::args().any(|a| a == "--verify");
+    let args = Vec::from_iter(std::env::args().filter(|a| !a.starts_with("--")));
 
-    for (i, (o_fast, o_slow)) in output_fast.zip(output_slow).enumerate() {
-        if o_fast != o_slow {
-            println!(
-                "Different output at line {}: o_fast={}, o_slow={}, record={:?}",
-                i + 1,
-                o_fast,
-                o_slow,
-                records[i]
-            );
+    // "nonogram <file>" solves a nonogram given by its row and column clues.
+    if args[1] == "nonogram" {
+        let src = std::fs::read_to_string(&args[2]).unwrap();
+        let puzzle = nonogram::parse_nonogram().parse(src).unwrap();
+        match nonogram::solve(&puzzle) {
+            nonogram::Solution::Unique(picture) => println!("{}", picture.join("\n")),
+            nonogram::Solution::Multiple(p1, p2) => println!(
+                "Multiple solutions, for instance:\n{}\n\n{}",
+                p1.join("\n"),
+                p2.join("\n")
+            ),
+            nonogram::Solution::Unsolvable => println!("No solution"),
         }
+        return;
+    }
+    let src = std::fs::read_to_string(&args[1]).unwrap();
+    let records = parse_spring_records().parse(src.clone()).unwrap();
+    let arg = |i: usize, default: usize| args.get(i).map(|a| a.parse().unwrap()).unwrap_or(default);
+
+    // "list <line> <factor> [limit]" prints the first arrangements of a record unfolded by
+    // the factor, "sample <line> <factor> [count]" draws some uniformly at random.
+    if let Some(command @ ("list" | "sample")) = args.get(2).map(|a| a.as_str()) {
+        let record = unfold(&records[arg(3, 1) - 1], arg(4, 1));
+        println!("{} arrangements", num_arrangements(&record));
+        let shown = if command == "list" {
+            Vec::from_iter(arrangements(&record).take(arg(5, 20)))
+        } else {
+            sample_arrangements(&record, arg(5, 10), &mut rand::thread_rng())
+        };
+        shown.iter().for_each(|a| println!("{}", a));
+        return;
+    }
+    let unfold_factor = arg(2, 5);
+    let counts: Vec<BigUint> = records.par_iter().map(num_arrangements).collect();
+
+    // Checking against the brute force and the full count table is opt-in, the brute force
+    // being exponential in the number of unknown tiles.
+    if verify {
+        let mismatches = records
+            .par_iter()
+            .zip(counts.par_iter())
+            .enumerate()
+            .filter_map(|(i, (record, count))| {
+                let o_slow = BigUint::from(brute_force_arrangements(record).count());
+                let o_table = &count_table(record)[0][0];
+                if *count != o_slow || count != o_table {
+                    Some(format!(
+                        "Different output at line {}: o_fast={}, o_table={}, o_slow={}, record={:?}",
+                        i + 1,
+                        count,
+                        o_table,
+                        o_slow,
+                        record
+                    ))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        mismatches.iter().for_each(|m| println!("{}", m));
+        println!(
+            "Verified {} records, {} mismatches",
+            records.len(),
+            mismatches.len()
+        );
     }
 
     println!(
         "Question 1 answer is: {}",
-        records
-            .iter()
-            .map(|r| num_arrangements_fast(r, 0, None, 0, &cache))
-            .sum::<u64>()
+        counts.into_iter().sum::<BigUint>()
     );
-
-    let expanded: Vec<SpringRecord> = records
-        .iter()
-        .map(|r| {
-            let mut new_sizes = Vec::new();
-            for _ in 0..5 {
-                new_sizes.append(&mut r.sizes.clone());
-            }
-            SpringRecord {
-                damaged: expand(r.damaged, r.total_size),
-                operational: expand(r.operational, r.total_size),
-                sizes: new_sizes,
-                total_size: r.total_size * 5 + 4,
-            }
-        })
-        .collect();
-
     println!(
         "Question 2 answer is: {}",
-        expanded
-            .into_iter()
-            .map(|r| num_arrangements_fast(&r, 0, None, 0, &cache))
-            .sum::<u64>()
+        records
+            .par_iter()
+            .map(|r| num_arrangements(&unfold(r, unfold_factor)))
+            .sum::<BigUint>()
     )
 }
@@ -0,0 +1,165 @@
+use chumsky::prelude::*;
+use num::Zero;
+
+use crate::{count_table, operational_before, place_group, render, SpringRecord, Tile};
+
+// Every row and every column of a nonogram is a spring record: the clues are the group sizes
+// and the cells filled so far are the known tiles.
+#[derive(Debug, Clone)]
+pub struct Nonogram {
+    rows: Vec<Vec<usize>>,
+    cols: Vec<Vec<usize>>,
+}
+
+type Grid = Vec<Vec<Option<Tile>>>;
+
+#[derive(Debug)]
+pub enum Solution {
+    Unique(Vec<String>),
+    Multiple(Vec<String>, Vec<String>),
+    Unsolvable,
+}
+
+// Row clues then column clues, separated by an empty line. Each line lists the group sizes
+// separated by commas, an empty line of the picture being written 0.
+pub fn parse_nonogram() -> impl Parser<char, Nonogram, Error = Simple<char>> {
+    let clue = text::int(10)
+        .map(|s: String| s.parse::<usize>().unwrap())
+        .separated_by(just(","))
+        .at_least(1)
+        .map(|sizes| Vec::from_iter(sizes.into_iter().filter(|s| *s > 0)));
+    let clues = clue.separated_by(text::newline()).at_least(1);
+    clues
+        .then_ignore(text::newline().repeated().at_least(2))
+        .then(clues)
+        .then_ignore(text::whitespace())
+        .then_ignore(end())
+        .map(|(rows, cols)| Nonogram { rows, cols })
+}
+
+// For each tile of a line, whether it is damaged in some arrangement and whether it is
+// operational in some arrangement. None when the line has no arrangement at all.
+fn line_possibilities(record: &SpringRecord) -> Option<Vec<(bool, bool)>> {
+    let table = count_table(record);
+    if table[0][0].is_zero() {
+        return None;
+    }
+    let operational_before = operational_before(record);
+    let num_tiles = record.tiles.len();
+    let num_groups = record.sizes.len();
+    let mut possible = vec![(false, false); num_tiles];
+    let mut reached = vec![vec![false; num_tiles + 1]; num_groups + 1];
+    reached[0][0] = true;
+
+    // Walk the states (group, position) that lie on some arrangement, in position order.
+    for p in 0..=num_tiles {
+        for g in 0..=num_groups {
+            if !reached[g][p] || table[g][p].is_zero() {
+                continue;
+            }
+            if g == num_groups {
+                possible[p..].iter_mut().for_each(|c| c.1 = true);
+                continue;
+            }
+            if p < num_tiles && record.tiles[p] != Some(Tile::D) && !table[g][p + 1].is_zero() {
+                possible[p].1 = true;
+                reached[g][p + 1] = true;
+            }
+            if p == num_tiles {
+                continue;
+            }
+            if let Some(next_p) = place_group(record, &operational_before, g, p) {
+                if !table[g + 1][next_p].is_zero() {
+                    let end = p + record.sizes[g];
+                    possible[p..end].iter_mut().for_each(|c| c.0 = true);
+                    possible[end..next_p].iter_mut().for_each(|c| c.1 = true);
+                    reached[g + 1][next_p] = true;
+                }
+            }
+        }
+    }
+    Some(possible)
+}
+
+// Fixes a line to the tiles shared by all of its arrangements. Returns whether anything
+// changed, or None on a contradiction.
+fn propagate_line(line: &mut [Option<Tile>], sizes: &[usize]) -> Option<bool> {
+    let record = SpringRecord {
+        tiles: line.to_vec(),
+        sizes: sizes.to_vec(),
+    };
+    let mut changed = false;
+
+    for (tile, possible) in line.iter_mut().zip(line_possibilities(&record)?) {
+        let forced = match possible {
+            (true, false) => Some(Tile::D),
+            (false, true) => Some(Tile::O),
+            _ => None,
+        };
+        if tile.is_none() && forced.is_some() {
+            *tile = forced;
+            changed = true;
+        }
+    }
+    Some(changed)
+}
+
+fn propagate(nonogram: &Nonogram, grid: &mut Grid) -> Option<()> {
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+        for (row, sizes) in grid.iter_mut().zip(nonogram.rows.iter()) {
+            changed |= propagate_line(row, sizes)?;
+        }
+        for (j, sizes) in nonogram.cols.iter().enumerate() {
+            let mut col = Vec::from_iter(grid.iter().map(|row| row[j]));
+            if propagate_line(&mut col, sizes)? {
+                changed = true;
+                grid.iter_mut().zip(col).for_each(|(row, t)| row[j] = t);
+            }
+        }
+    }
+    Some(())
+}
+
+// Propagates line constraints until they are stuck, then guesses the first unknown cell.
+// Stops as soon as two solutions are found.
+fn backtrack(nonogram: &Nonogram, mut grid: Grid, solutions: &mut Vec<Grid>) {
+    if solutions.len() >= 2 || propagate(nonogram, &mut grid).is_none() {
+        return;
+    }
+    let unknown = grid
+        .iter()
+        .enumerate()
+        .find_map(|(i, row)| row.iter().position(|t| t.is_none()).map(|j| (i, j)));
+    match unknown {
+        None => solutions.push(grid),
+        Some((i, j)) => {
+            for tile in [Tile::D, Tile::O] {
+                let mut guess = grid.clone();
+                guess[i][j] = Some(tile);
+                backtrack(nonogram, guess, solutions);
+            }
+        }
+    }
+}
+
+fn picture(grid: &Grid) -> Vec<String> {
+    Vec::from_iter(
+        grid.iter()
+            .map(|row| render(&Vec::from_iter(row.iter().map(|t| t.unwrap())))),
+    )
+}
+
+pub fn solve(nonogram: &Nonogram) -> Solution {
+    let grid = vec![vec![None; nonogram.cols.len()]; nonogram.rows.len()];
+    let mut solutions = Vec::new();
+    backtrack(nonogram, grid, &mut solutions);
+
+    match &solutions[..] {
+        [] => Solution::Unsolvable,
+        [solution] => Solution::Unique(picture(solution)),
+        [s1, s2, ..] => Solution::Multiple(picture(s1), picture(s2)),
+    }
+}
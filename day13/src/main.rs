@@ -1,154 +1,171 @@
-use std::collections::HashSet;
 use std::ops::Range;
 
 use chumsky::prelude::*;
-use itertools::{Either, Itertools};
+
+mod symmetry;
 
 type Scalar = i32;
 
-type Coord = [Scalar; 2];
+// Rocks of a line as a bitmask, so patterns are at most 64 tiles wide and high.
+type Mask = u64;
 
+// Each row is stored as the mask of its rock columns, and each column as the mask of its
+// rock rows, so that comparing two lines is a single xor in both directions.
 #[derive(Debug, Clone)]
 struct Pattern {
-    rocks: HashSet<Coord>,
-    shape: Coord,
+    rows: Vec<Mask>,
+    cols: Vec<Mask>,
+}
+
+impl Pattern {
+    // Fails for patterns whose lines do not fit in a mask.
+    pub fn from_rows(rows: Vec<Vec<bool>>) -> Result<Pattern, String> {
+        let num_cols = rows[0].len();
+        if rows.len() > Mask::BITS as usize || num_cols > Mask::BITS as usize {
+            return Err(format!(
+                "{} by {} tiles is larger than {} tiles",
+                rows.len(),
+                num_cols,
+                Mask::BITS
+            ));
+        }
+        let to_mask = |line: &mut dyn Iterator<Item = bool>| {
+            line.enumerate()
+                .fold(0, |mask, (i, is_rock)| mask | ((is_rock as Mask) << i))
+        };
+        Ok(Pattern {
+            rows: Vec::from_iter(rows.iter().map(|row| to_mask(&mut row.iter().copied()))),
+            cols: Vec::from_iter(
+                (0..num_cols).map(|j| to_mask(&mut rows.iter().map(|row| row[j]))),
+            ),
+        })
+    }
 }
 
-fn parse_patterns() -> impl Parser<char, Vec<(usize, Pattern)>, Error = Simple<char>> {
-    let tile = one_of("#.").map_with_span(|c: char, span: Range<usize>| match c {
-        '#' => (true, span.start),
-        _ => (false, span.start),
-    });
+// Patterns as rows of tiles, which are turned into masks once their size is checked.
+fn parse_patterns() -> impl Parser<char, Vec<(usize, Vec<Vec<bool>>)>, Error = Simple<char>> {
+    let tile = one_of("#.").map(|c: char| c == '#');
     let tile_row = tile.repeated().at_least(1);
-    let pattern = tile_row
-        .separated_by(text::newline())
-        .at_least(1)
-        .map_with_span(|rows, pat_span: Range<usize>| {
-            let num_rows = rows.len();
-            let num_cols = rows[0].len();
-            let rocks = rows.into_iter().flatten().filter_map(|(is_rock, abs_pos)| {
-                if is_rock {
-                    Some({
-                        let rel_pos = abs_pos - pat_span.start;
-                        [
-                            2 * (rel_pos / (num_cols + 1)) as i32,
-                            2 * (rel_pos % (num_cols + 1)) as i32,
-                        ]
-                    })
-                } else {
-                    None
-                }
-            });
-            Pattern {
-                rocks: rocks.collect(),
-                shape: [(num_rows * 2) as i32, (num_cols * 2) as i32],
-            }
-        });
+    let pattern = tile_row.separated_by(text::newline()).at_least(1);
     pattern
         .map_with_span(|v, s: Range<usize>| (s.start, v))
         .separated_by(text::whitespace().at_least(1))
 }
 
-fn is_symmetric_around_axis(pattern: &Pattern, axis: Scalar, dim: usize) -> bool {
-    let (left, right): (HashSet<Coord>, HashSet<Coord>) = pattern.rocks.iter().partition_map(|c| {
-        let mut c = *c;
-        c[dim] -= axis;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Reflection {
+    // Mirror between rows (or columns) index - 1 and index.
+    horizontal: bool,
+    index: usize,
+    // Number of cells that differ from their mirror image.
+    mismatches: u32,
+}
 
-        if c[dim] < 0 {
-            c[dim] *= -1;
-            Either::Left(c)
+impl Reflection {
+    pub fn summary(&self) -> Scalar {
+        if self.horizontal {
+            100 * self.index as Scalar
         } else {
-            Either::Right(c)
+            self.index as Scalar
         }
-    });
-    let max_coord = std::cmp::min(axis, pattern.shape[dim] - axis - 1);
-    let left_filtered: HashSet<Coord> = left
-        .iter()
-        .filter_map(|c| if c[dim] <= max_coord { Some(*c) } else { None })
-        .collect();
-    let right_filtered = right
-        .iter()
-        .filter_map(|c| if c[dim] <= max_coord { Some(*c) } else { None })
-        .collect();
-    let out = left_filtered == right_filtered;
-    out
+    }
 }
 
-fn find_symmetries<'a>(pattern: &'a Pattern, dim: usize) -> impl Iterator<Item = Scalar> + 'a {
-    (1..pattern.shape[dim] - 1).filter_map(move |i| {
-        if i % 2 == 1 && is_symmetric_around_axis(pattern, i, dim) {
-            Some(i / 2 + 1)
-        } else {
-            None
-        }
+fn line_reflections(lines: &[Mask], horizontal: bool) -> impl Iterator<Item = Reflection> + '_ {
+    (1..lines.len()).map(move |index| Reflection {
+        horizontal,
+        index,
+        mismatches: lines[..index]
+            .iter()
+            .rev()
+            .zip(lines[index..].iter())
+            .map(|(l1, l2)| (l1 ^ l2).count_ones())
+            .sum(),
     })
 }
 
-fn summarize_pattern<'a>(
-    pattern: &'a Pattern,
-) -> impl Iterator<Item = (Scalar, bool, Scalar)> + 'a {
-    find_symmetries(pattern, 0)
-        .map(|row| (100 * row, true, row))
-        .chain(find_symmetries(pattern, 1).map(|col| (col, false, col)))
+// Every horizontal then vertical mirror axis, whether or not it is a perfect reflection.
+fn reflections(pattern: &Pattern) -> impl Iterator<Item = Reflection> + '_ {
+    line_reflections(&pattern.rows, true).chain(line_reflections(&pattern.cols, false))
 }
 
-fn fix_smudge_summarize(pattern: &Pattern) -> Option<Scalar> {
-    let mut new_summary = None;
-    let old_summary = summarize_pattern(pattern).next().unwrap();
-
-    'outer: for i in 0..pattern.shape[0] {
-        if i % 2 == 0 {
-            for j in 0..pattern.shape[1] {
-                if j % 2 == 0 {
-                    let k = [i, j];
-                    let mut new_rocks = pattern.rocks.clone();
-                    if pattern.rocks.contains(&k) {
-                        new_rocks.remove(&k);
-                    } else {
-                        new_rocks.insert(k);
-                    }
-                    let candidate = Pattern {
-                        rocks: new_rocks,
-                        shape: pattern.shape,
-                    };
-                    new_summary = summarize_pattern(&candidate)
-                        .filter(|new_s| *new_s != old_summary)
-                        .next();
-                    if new_summary.is_some() {
-                        break 'outer;
-                    }
-                }
+// Summaries for patterns with 0 up to max_smudges smudges, indexed by the number of smudges.
+// Each pattern contributes its first reflection with exactly that many mismatches.
+fn smudge_summaries(patterns: &[(usize, Pattern)], max_smudges: usize) -> Vec<Option<Scalar>> {
+    let mut totals = vec![Some(0); max_smudges + 1];
+
+    for (_, pattern) in patterns {
+        let mut summaries = vec![None; max_smudges + 1];
+        for reflection in reflections(pattern) {
+            let k = reflection.mismatches as usize;
+            if k <= max_smudges && summaries[k].is_none() {
+                summaries[k] = Some(reflection.summary());
             }
         }
+        for (total, summary) in totals.iter_mut().zip(summaries) {
+            *total = total.zip(summary).map(|(t, s)| t + s);
+        }
     }
-    new_summary.map(|t| t.0)
+    totals
 }
 
 fn main() {
     let src = std::fs::read_to_string(std::env::args().nth(1).unwrap()).unwrap();
-    let patterns = parse_patterns().parse(src.clone()).unwrap();
-
-    println!(
-        "Question 1 answer is: {}",
-        patterns
-            .iter()
-            .map(|(_, p)| summarize_pattern(p).next().unwrap().0)
-            .sum::<i32>()
-    );
-    println!(
-        "Question 2 answer is: {}",
-        patterns
-            .iter()
-            .map(|(i, p)| {
-                let opt_sum = fix_smudge_summarize(p);
-                match opt_sum {
-                    Some(j) => j,
-                    None => {
-                        let num_lines = src[0..*i].chars().filter(|c| *c == '\n').count();
-                        panic!("Did not find smudge for pattern at line {num_lines}")
-                    }
+    let line_of = |i: usize| src[0..i].chars().filter(|c| *c == '\n').count();
+    let patterns = Vec::from_iter(
+        parse_patterns()
+            .parse(src.clone())
+            .unwrap()
+            .into_iter()
+            .map(|(i, rows)| match Pattern::from_rows(rows) {
+                Ok(pattern) => (i, pattern),
+                Err(err) => {
+                    println!("Pattern at line {} is not supported: {}", line_of(i), err);
+                    std::process::exit(1);
                 }
-            })
-            .sum::<i32>()
+            }),
     );
+
+    // "symmetries [max mismatches]" lists every kind of symmetry found in each pattern.
+    if std::env::args().nth(2).as_deref() == Some("symmetries") {
+        let max_mismatches: u32 = std::env::args()
+            .nth(3)
+            .map(|k| k.parse().unwrap())
+            .unwrap_or(0);
+        for (n, (_, pattern)) in patterns.iter().enumerate() {
+            println!("Pattern {}:", n + 1);
+            for candidate in symmetry::symmetries(pattern, max_mismatches) {
+                println!(
+                    "  {:?} with {} mismatches",
+                    candidate.symmetry, candidate.mismatches
+                );
+            }
+        }
+        return;
+    }
+    let max_smudges: usize = std::env::args()
+        .nth(2)
+        .map(|k| k.parse().unwrap())
+        .unwrap_or(1);
+
+    for (i, pattern) in patterns.iter() {
+        for k in 0..=max_smudges {
+            if !reflections(pattern).any(|r| r.mismatches as usize == k) {
+                let num_lines = line_of(*i);
+                println!("No reflection with {k} smudges for pattern at line {num_lines}");
+            }
+        }
+    }
+    // Both questions are answered whatever the number of smudges asked for.
+    let summaries = smudge_summaries(&patterns, max_smudges.max(1));
+    let answer = |k: usize| match summaries[k] {
+        Some(s) => s.to_string(),
+        None => "none".to_string(),
+    };
+
+    println!("Question 1 answer is: {}", answer(0));
+    println!("Question 2 answer is: {}", answer(1));
+    for k in 2..=max_smudges {
+        println!("Answer with {} smudges is: {}", k, answer(k));
+    }
 }
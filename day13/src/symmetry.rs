@@ -0,0 +1,130 @@
+use crate::{reflections, Mask, Pattern, Reflection};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Symmetry {
+    Mirror(Reflection),
+    // Half turn of the whole pattern around its center, given as (row, column).
+    HalfTurn {
+        center: (f64, f64),
+    },
+    // Quarter turn of the square of the given size whose top left tile is at (row, column).
+    QuarterTurn {
+        top_left: (usize, usize),
+        size: usize,
+    },
+    // Reflection of a square across its main diagonal, or its anti-diagonal when `anti`.
+    Diagonal {
+        top_left: (usize, usize),
+        size: usize,
+        anti: bool,
+    },
+}
+
+// A candidate symmetry and the number of cells that would have to change to make it exact.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub symmetry: Symmetry,
+    pub mismatches: u32,
+}
+
+impl Pattern {
+    pub fn is_rock(&self, i: usize, j: usize) -> bool {
+        self.rows[i] >> j & 1 == 1
+    }
+
+    // Each pair of cells swapped by the half turn and differing needs one change.
+    pub fn half_turn_mismatches(&self) -> u32 {
+        let num_cols = self.cols.len() as u32;
+        let reversed = |row: Mask| row.reverse_bits() >> (Mask::BITS - num_cols);
+        let num_rows = self.rows.len();
+        let pairs: u32 = (0..num_rows / 2)
+            .map(|i| (self.rows[i] ^ reversed(self.rows[num_rows - 1 - i])).count_ones())
+            .sum();
+        let middle = if num_rows % 2 == 1 {
+            let row = self.rows[num_rows / 2];
+            (row ^ reversed(row)).count_ones() / 2
+        } else {
+            0
+        };
+        pairs + middle
+    }
+
+    // Cells of a square come in orbits of four under quarter turns, and an orbit needs its
+    // minority cells changed.
+    pub fn quarter_turn_mismatches(&self, (top, left): (usize, usize), size: usize) -> u32 {
+        let cell = |i: usize, j: usize| self.is_rock(top + i, left + j) as u32;
+        let mut mismatches = 0;
+
+        for i in 0..size.div_ceil(2) {
+            for j in 0..size / 2 {
+                let num_rocks = cell(i, j)
+                    + cell(j, size - 1 - i)
+                    + cell(size - 1 - i, size - 1 - j)
+                    + cell(size - 1 - j, i);
+                mismatches += num_rocks.min(4 - num_rocks);
+            }
+        }
+        mismatches
+    }
+
+    pub fn diagonal_mismatches(&self, (top, left): (usize, usize), size: usize, anti: bool) -> u32 {
+        let cell = |i: usize, j: usize| self.is_rock(top + i, left + j);
+        let mut mismatches = 0;
+
+        for i in 0..size {
+            for j in 0..size {
+                let (mi, mj) = if anti {
+                    (size - 1 - j, size - 1 - i)
+                } else {
+                    (j, i)
+                };
+                // Count each pair once, from its cell with the smallest coordinates.
+                if (i, j) < (mi, mj) && cell(i, j) != cell(mi, mj) {
+                    mismatches += 1;
+                }
+            }
+        }
+        mismatches
+    }
+}
+
+// All candidate symmetries with at most max_mismatches mismatched cells. Quarter turns and
+// diagonal reflections are looked for on every square sub-region of at least two tiles.
+pub fn symmetries(pattern: &Pattern, max_mismatches: u32) -> Vec<Candidate> {
+    let num_rows = pattern.rows.len();
+    let num_cols = pattern.cols.len();
+    let mut candidates = Vec::from_iter(reflections(pattern).map(|r| Candidate {
+        symmetry: Symmetry::Mirror(r),
+        mismatches: r.mismatches,
+    }));
+
+    candidates.push(Candidate {
+        symmetry: Symmetry::HalfTurn {
+            center: ((num_rows - 1) as f64 / 2., (num_cols - 1) as f64 / 2.),
+        },
+        mismatches: pattern.half_turn_mismatches(),
+    });
+    for size in 2..=num_rows.min(num_cols) {
+        for top in 0..=num_rows - size {
+            for left in 0..=num_cols - size {
+                let top_left = (top, left);
+                candidates.push(Candidate {
+                    symmetry: Symmetry::QuarterTurn { top_left, size },
+                    mismatches: pattern.quarter_turn_mismatches(top_left, size),
+                });
+                for anti in [false, true] {
+                    candidates.push(Candidate {
+                        symmetry: Symmetry::Diagonal {
+                            top_left,
+                            size,
+                            anti,
+                        },
+                        mismatches: pattern.diagonal_mismatches(top_left, size, anti),
+                    });
+                }
+            }
+        }
+    }
+    candidates.retain(|c| c.mismatches <= max_mismatches);
+    candidates
+}
@@ -1,39 +1,67 @@
-#![feature(btree_cursors)]
 use chumsky::prelude::*;
+use cycle::Cycle;
 
 use std::ops::Range;
-use std::{
-    collections::{BTreeMap, HashMap},
-    ops::Bound,
-};
+use std::path::Path;
+
+mod record;
 
 type Scalar = i32;
 
 type Coord = [Scalar; 2];
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+// Rocks of a row or a column as a bitboard, so platforms are at most 128 tiles wide and high.
+type Line = u128;
+
+// Round rocks are stored by row, bit j of a row being set for a rock in column j. Cube rocks
+// never move, so they are stored both by row and by column, in that order.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct Platform {
-    row_col_obstacles: [Vec<BTreeMap<Scalar, Rock>>; 2],
-    o_rocks: Vec<Coord>,
+    round_rows: Vec<Line>,
+    cube_lines: [Vec<Line>; 2],
     shape: Coord,
 }
 
 impl Platform {
-    pub fn get_obstacles(&mut self, dim: usize, idx: i32) -> &mut BTreeMap<Scalar, Rock> {
-        self.row_col_obstacles
-            .get_mut(dim)
-            .and_then(|col_obst| col_obst.get_mut(idx as usize))
-            .unwrap()
+    pub fn round_rocks(&self) -> impl Iterator<Item = Coord> + '_ {
+        self.round_rows
+            .iter()
+            .enumerate()
+            .flat_map(|(i, row)| set_bits(*row).map(move |j| [i as Scalar, j as Scalar]))
     }
 }
 
-#[derive(Debug, PartialEq, PartialOrd, Ord, Eq, Clone, Copy)]
+// Indices of the set bits of a line, in increasing order.
+fn set_bits(mut line: Line) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if line == 0 {
+            return None;
+        }
+        let k = line.trailing_zeros() as usize;
+        line &= line - 1;
+        Some(k)
+    })
+}
+
+// Lines of the other orientation: bit i of line j is set when bit j of line i is.
+fn transpose(lines: &[Line], num_lines: usize) -> Vec<Line> {
+    let mut transposed = vec![0; num_lines];
+    for (i, line) in lines.iter().enumerate() {
+        set_bits(*line).for_each(|j| transposed[j] |= 1 << i);
+    }
+    transposed
+}
+
+#[derive(Debug, PartialEq, PartialOrd, Ord, Eq, Hash, Clone, Copy)]
 enum Rock {
     O,
     C,
 }
 
-fn parse_rocks() -> impl Parser<char, Vec<(Vec<(Rock, Coord)>, Scalar)>, Error = Simple<char>> {
+// Rocks of a row with their coordinates, and the number of columns.
+type RockRow = (Vec<(Rock, Coord)>, Scalar);
+
+fn parse_rocks() -> impl Parser<char, Vec<RockRow>, Error = Simple<char>> {
     let tile = one_of("O.#").map_with_span(|c: char, tile_span: Range<usize>| {
         (
             match c {
@@ -66,32 +94,34 @@ fn parse_rocks() -> impl Parser<char, Vec<(Vec<(Rock, Coord)>, Scalar)>, Error =
     .separated_by(text::newline())
 }
 
-fn rocks_to_platform(rocks: Vec<(Vec<(Rock, Coord)>, Scalar)>) -> Platform {
+// Fails for platforms whose rows or columns do not fit in a line.
+fn rocks_to_platform(rocks: Vec<RockRow>) -> Result<Platform, String> {
     let num_rows = rocks.len();
-    let num_cols = rocks[0].1;
-    let mut row_col_obstacles: [Vec<BTreeMap<Scalar, Rock>>; 2] = [
-        Vec::from_iter((0..num_cols).map(|_| BTreeMap::new())),
-        Vec::from_iter((0..num_cols).map(|_| BTreeMap::new())),
-    ];
-    let mut o_rocks = Vec::new();
-
-    for (rock, c) in rocks.iter().map(|t| &t.0).flatten() {
-        for dim1 in 0_usize..=1 {
-            let dim2 = 1 - dim1;
-            let j = c[dim1];
-            let i = c[dim2];
-            row_col_obstacles[dim1][j as usize].insert(i, *rock);
-            if *rock == Rock::O && dim1 == 0 {
-                o_rocks.push(*c);
-            }
-        }
+    let num_cols = rocks[0].1 as usize;
+    if num_rows > Line::BITS as usize || num_cols > Line::BITS as usize {
+        return Err(format!(
+            "{} by {} tiles is larger than {} tiles",
+            num_rows,
+            num_cols,
+            Line::BITS
+        ));
     }
+    let mut round_rows = vec![0; num_rows];
+    let mut cube_rows = vec![0; num_rows];
 
-    Platform {
-        row_col_obstacles,
-        o_rocks,
-        shape: [num_rows as Scalar, num_cols as Scalar],
+    for (rock, [i, j]) in rocks.iter().flat_map(|t| &t.0) {
+        let rows = match rock {
+            Rock::O => &mut round_rows,
+            Rock::C => &mut cube_rows,
+        };
+        rows[*i as usize] |= 1 << j;
     }
+
+    Ok(Platform {
+        round_rows,
+        cube_lines: [cube_rows.clone(), transpose(&cube_rows, num_cols)],
+        shape: [num_rows as Scalar, num_cols as Scalar],
+    })
 }
 
 type Direction = (usize, Scalar);
@@ -101,113 +131,165 @@ const SOUTH: Direction = (0, 1);
 const WEST: Direction = (1, -1);
 const EAST: Direction = (1, 1);
 
-fn tilt(platform: &mut Platform, (dim1, dir): Direction) {
-    let shape = platform.shape;
-    let mut orig_o_rocks = platform.o_rocks.clone();
-    // When going north, need to iterate from top to bottom, etc.
-    orig_o_rocks.sort_by_key(|c| c[dim1] * -dir);
-    let mut new_o_rocks = Vec::new();
-    // NORTH: dim1 = 0, dir = -1
-    for c in orig_o_rocks {
-        // NORTH: dim2 = 1
-        let dim2 = 1 - dim1;
-        // NORTH: j is column index, i is row index
-        let col_idx = c[dim2];
-        let orig_row_idx = c[dim1];
-        // North: dim2 == 1 so this is the column btree.
-        let col_btree = platform.get_obstacles(dim2, col_idx);
-        col_btree.remove(&orig_row_idx);
-        let new_row;
-        // North: dir < 0 so upper bound of row_idx excluded
-        let mut obstacle_cursor = if dir < 0 {
-            col_btree.upper_bound_mut(Bound::Excluded(&orig_row_idx))
-        } else {
-            col_btree.lower_bound_mut(Bound::Excluded(&orig_row_idx))
+// Line with its n lowest bits set, n going up to the full width.
+fn low_bits(n: usize) -> Line {
+    Line::MAX.checked_shr(Line::BITS - n as u32).unwrap_or(0)
+}
+
+// Packs the round rocks of every stretch between two cube rocks at one end of the stretch,
+// towards bit 0 when `to_start`.
+fn tilt_line(round: Line, cube: Line, len: usize, to_start: bool) -> Line {
+    let mut tilted = 0;
+    let mut start = 0;
+
+    while start < len {
+        let end = match cube >> start {
+            0 => len,
+            rest => start + rest.trailing_zeros() as usize,
         };
-        // North: dir == -1 so -dir works out to +1.
-        let border = if dir < 0 { -1 } else { shape[dim1] };
-        new_row = obstacle_cursor.key().map(|t| *t).unwrap_or(border) - dir;
-        let mut new_o_rock = c;
-        new_o_rock[dim1] = new_row;
-        new_o_rocks.push(new_o_rock);
-        // North: dir < 0 so we insert after at the new row.
-        if dir < 0 {
-            obstacle_cursor.insert_after(new_row, Rock::O);
-        } else {
-            obstacle_cursor.insert_before(new_row, Rock::O);
+        let num_rocks = (round & low_bits(end) & !low_bits(start)).count_ones() as usize;
+        // A stretch without round rocks may end past the last bit, where shifting overflows.
+        if num_rocks > 0 {
+            let offset = if to_start { start } else { end - num_rocks };
+            tilted |= low_bits(num_rocks) << offset;
         }
-        // Updating the other btree.
-        let old_row_btree = platform.get_obstacles(dim1, orig_row_idx);
-        old_row_btree.remove(&col_idx);
-        let new_row_btree = platform.get_obstacles(dim1, new_row);
-        new_row_btree.insert(col_idx, Rock::O);
+        start = end + 1;
     }
-    platform.o_rocks = new_o_rocks;
+    tilted
 }
 
-fn total_load_north(plaform: &Platform) -> Scalar {
-    plaform
-        .o_rocks
-        .iter()
-        .map(|[i, _]| plaform.shape[0] - i)
-        .sum()
+fn tilt(platform: &mut Platform, (dim, dir): Direction) {
+    let [num_rows, num_cols] = platform.shape.map(|n| n as usize);
+    // Going north or south moves rocks along columns, west or east along rows.
+    let (lines, len) = if dim == 0 {
+        (transpose(&platform.round_rows, num_cols), num_rows)
+    } else {
+        (platform.round_rows.clone(), num_cols)
+    };
+    let cube_lines = &platform.cube_lines[1 - dim];
+    let tilted = Vec::from_iter(
+        lines
+            .iter()
+            .zip(cube_lines)
+            .map(|(round, cube)| tilt_line(*round, *cube, len, dir < 0)),
+    );
+
+    platform.round_rows = if dim == 0 {
+        transpose(&tilted, num_rows)
+    } else {
+        tilted
+    };
 }
 
-fn visualize(plaform: &Platform) {
-    for i in 0..plaform.shape[0] {
-        for j in 0..plaform.shape[1] {
-            let s = match plaform.row_col_obstacles[1][j as usize].get(&i) {
-                None => ".",
-                Some(Rock::O) => "O",
-                Some(Rock::C) => "#",
-            };
-            print!("{s}");
-        }
-        println!("");
+fn parse_direction(c: char) -> Result<Direction, String> {
+    match c {
+        'N' => Ok(NORTH),
+        'S' => Ok(SOUTH),
+        'W' => Ok(WEST),
+        'E' => Ok(EAST),
+        _ => Err(format!("unknown direction {}", c)),
+    }
+}
+
+// A spin program is a sequence of tilts such as "NWSE".
+fn parse_program(program: &str) -> Result<Vec<Direction>, String> {
+    program.chars().map(parse_direction).collect()
+}
+
+fn run_program(platform: &Platform, program: &[Direction]) -> Platform {
+    let mut platform = platform.clone();
+    for dir in program {
+        tilt(&mut platform, *dir);
     }
-    println!("");
+    platform
+}
+
+// Each rock weighs the number of rows (or columns) from it to the edge opposite to the given
+// one, its own included.
+fn total_load(platform: &Platform, (dim, dir): Direction) -> i64 {
+    platform
+        .round_rocks()
+        .map(|c| {
+            if dir < 0 {
+                platform.shape[dim] - c[dim]
+            } else {
+                c[dim] + 1
+            }
+        } as i64)
+        .sum()
 }
 
 fn main() {
-    let src = std::fs::read_to_string(std::env::args().nth(1).unwrap()).unwrap();
-    let platform = rocks_to_platform(parse_rocks().parse(src).unwrap());
+    // Flags can appear anywhere, the remaining arguments are positional.
+    let flag = |name: &str| std::env::args().any(|a| a == name);
+    let flag_value = |name: &str| {
+        std::env::args().find_map(|a| a.strip_prefix(&format!("{}=", name)).map(String::from))
+    };
+    let args = Vec::from_iter(std::env::args().filter(|a| !a.starts_with("--")));
+    let src = std::fs::read_to_string(&args[1]).unwrap();
+    let platform = match rocks_to_platform(parse_rocks().parse(src).unwrap()) {
+        Ok(platform) => platform,
+        Err(err) => {
+            println!("Platform is not supported: {}", err);
+            std::process::exit(1);
+        }
+    };
     let mut platform_q1 = platform.clone();
     tilt(&mut platform_q1, NORTH);
 
-    println!("Question 1 answer is: {}", total_load_north(&platform_q1));
-    let mut platform_q2 = platform;
-    let num_iter = 4000000000;
-    let mut dir_iter = [NORTH, WEST, SOUTH, EAST]
-        .into_iter()
-        .cycle()
-        .take(num_iter)
-        .enumerate();
-    let mut state_cache: HashMap<(Direction, Vec<Coord>), usize> = HashMap::new();
-    let mut cycle_start_end = None;
-
-    while let Some((i, dir)) = dir_iter.next() {
-        tilt(&mut platform_q2, dir);
-        let mut rock_coords = platform_q2.o_rocks.clone();
-        rock_coords.sort();
-        let state_key = (dir, rock_coords);
-        if let Some(start) = state_cache.get(&state_key) {
-            cycle_start_end = Some((*start, i));
-            break;
+    println!("Question 1 answer is: {}", total_load(&platform_q1, NORTH));
+
+    // "[program [repetitions [edge]]]" runs a spin program and measures the load on an edge.
+    let program_text = args.get(2).map_or("NWSE", |a| a.as_str());
+    let program = parse_program(program_text).unwrap();
+    let num_runs: u64 = args.get(3).map_or(1000000000, |a| a.parse().unwrap());
+    let edge = parse_direction(args.get(4).map_or('N', |a| a.chars().next().unwrap())).unwrap();
+    let history = cycle::hashing(platform.clone(), |p| run_program(p, &program));
+    let Cycle { prefix_len, period } = history.cycle;
+    let final_platform = history.state_at(num_runs);
+
+    if flag("--show") {
+        println!(
+            "After {} runs the platform repeats every {} runs",
+            prefix_len, period
+        );
+        println!("{}", record::render_grid(final_platform));
+    }
+
+    // "--cast=<file>" and "--ppm=<directory>" record every tilt until the platform is back to
+    // an earlier state, or until the program has run the requested number of times.
+    let cast_path = flag_value("--cast");
+    let ppm_dir = flag_value("--ppm");
+    if cast_path.is_some() || ppm_dir.is_some() {
+        let num_recorded = num_runs.min(history.cycle.num_distinct_states());
+        let recorder = record::record_runs(&platform, program_text, num_recorded);
+        if let Some(path) = cast_path {
+            std::fs::write(&path, record::asciicast(&recorder, 0.2)).unwrap();
+            println!("Wrote {} frames to {}", recorder.frames.len(), path);
+        }
+        if let Some(dir) = ppm_dir {
+            let num_frames = record::write_ppm_frames(&recorder, Path::new(&dir), 8).unwrap();
+            println!("Wrote {} frames to {}", num_frames, dir);
         }
-        state_cache.insert(state_key, i);
     }
 
-    // Found a cycle, fast forwarding.
-    let (start, end) = cycle_start_end.unwrap();
-    let remaining_steps = num_iter - end;
-    let fast_forward_amount = (remaining_steps / (end - start)) * (end - start);
-    let mut i = end + fast_forward_amount + 1;
+    println!("Question 2 answer is: {}", total_load(final_platform, edge));
+}
 
-    while i < num_iter {
-        let (_, dir) = dir_iter.next().unwrap();
-        tilt(&mut platform_q2, dir);
-        i += 1;
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    println!("Question 2 answer is: {}", total_load_north(&platform_q2));
+    #[test]
+    fn tilt_line_full_width() {
+        let len = Line::BITS as usize;
+        // "O#" followed by empty tiles up to the last bit.
+        let (round, cube) = (0b01, 0b10);
+        assert_eq!(tilt_line(round, cube, len, true), round);
+        assert_eq!(tilt_line(round, cube, len, false), round);
+        // A single rock at each end of an empty line.
+        let round = 1 | 1 << (len - 1);
+        assert_eq!(tilt_line(round, 0, len, true), 0b11);
+        assert_eq!(tilt_line(round, 0, len, false), 0b11 << (len - 2));
+    }
 }
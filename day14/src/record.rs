@@ -0,0 +1,164 @@
+use std::path::Path;
+
+use crate::{parse_direction, tilt, total_load, Platform, EAST, NORTH, SOUTH, WEST};
+
+const ROUND_COLOR: [u8; 3] = [205, 133, 63];
+const CUBE_COLOR: [u8; 3] = [64, 64, 64];
+const EMPTY_COLOR: [u8; 3] = [235, 235, 235];
+const LOAD_COLOR: [u8; 3] = [70, 130, 180];
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub platform: Platform,
+    pub caption: String,
+}
+
+// Platforms captured one after the other, typically after every tilt of a spin program.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    pub frames: Vec<Frame>,
+}
+
+impl Recorder {
+    pub fn capture(&mut self, platform: &Platform, caption: String) {
+        self.frames.push(Frame {
+            platform: platform.clone(),
+            caption,
+        });
+    }
+}
+
+// Runs the spin program the given number of times, capturing the platform after every tilt.
+pub fn record_runs(platform: &Platform, program: &str, num_runs: u64) -> Recorder {
+    let mut recorder = Recorder::default();
+    let mut platform = platform.clone();
+    recorder.capture(&platform, "start".to_string());
+
+    for run in 1..=num_runs {
+        for c in program.chars() {
+            tilt(&mut platform, parse_direction(c).unwrap());
+            recorder.capture(&platform, format!("run {} tilt {}", run, c));
+        }
+    }
+    recorder
+}
+
+pub fn render_grid(platform: &Platform) -> String {
+    let mut rendered = String::new();
+
+    for (round, cube) in platform.round_rows.iter().zip(&platform.cube_lines[0]) {
+        for j in 0..platform.shape[1] {
+            rendered.push(match (round >> j & 1, cube >> j & 1) {
+                (1, _) => 'O',
+                (_, 1) => '#',
+                _ => '.',
+            });
+        }
+        rendered.push('\n');
+    }
+    rendered
+}
+
+// Caption, grid, then the load on each of the four edges.
+pub fn render_frame(frame: &Frame) -> String {
+    let loads = [('N', NORTH), ('W', WEST), ('S', SOUTH), ('E', EAST)]
+        .map(|(name, edge)| format!("{} {}", name, total_load(&frame.platform, edge)));
+    format!(
+        "{}\n{}load {}\n",
+        frame.caption,
+        render_grid(&frame.platform),
+        loads.join(" ")
+    )
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// Asciicast v2 recording where every frame clears the terminal and is shown for the given
+// number of seconds.
+pub fn asciicast(recorder: &Recorder, frame_duration: f64) -> String {
+    let Some(first) = recorder.frames.first() else {
+        return String::new();
+    };
+    // Caption and load lines around the grid, which are at most a few dozen columns wide. The
+    // cursor ends on the line after the load, which needs a row of its own to avoid scrolling.
+    let width = (first.platform.shape[1] as usize).max(40);
+    let height = first.platform.shape[0] as usize + 3;
+    let mut cast = format!(
+        "{{\"version\": 2, \"width\": {}, \"height\": {}}}\n",
+        width, height
+    );
+
+    for (k, frame) in recorder.frames.iter().enumerate() {
+        let screen = format!("\x1b[H\x1b[2J{}", render_frame(frame).replace('\n', "\r\n"));
+        cast.push_str(&format!(
+            "[{:.3}, \"o\", {}]\n",
+            k as f64 * frame_duration,
+            json_string(&screen)
+        ));
+    }
+    cast
+}
+
+// Binary PPM image with every tile drawn as a square of the given size. A bar under the
+// platform shows the north load relative to the largest one of the recording.
+fn ppm(frame: &Frame, tile_size: usize, max_load: i64) -> Vec<u8> {
+    let [num_rows, num_cols] = frame.platform.shape.map(|n| n as usize);
+    let (width, height) = (num_cols * tile_size, (num_rows + 1) * tile_size);
+    let grid = Vec::from_iter(
+        render_grid(&frame.platform)
+            .lines()
+            .map(|l| l.as_bytes().to_vec()),
+    );
+    let load = total_load(&frame.platform, NORTH);
+    let bar_width = (width as i64 * load / max_load.max(1)) as usize;
+    let mut image = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+
+    for y in 0..height {
+        for x in 0..width {
+            let color = match grid.get(y / tile_size).map(|row| row[x / tile_size]) {
+                Some(b'O') => ROUND_COLOR,
+                Some(b'#') => CUBE_COLOR,
+                Some(_) => EMPTY_COLOR,
+                None if x < bar_width => LOAD_COLOR,
+                None => EMPTY_COLOR,
+            };
+            image.extend(color);
+        }
+    }
+    image
+}
+
+// Writes one numbered PPM file per frame into the directory, returning the number of files.
+pub fn write_ppm_frames(
+    recorder: &Recorder,
+    dir: &Path,
+    tile_size: usize,
+) -> std::io::Result<usize> {
+    std::fs::create_dir_all(dir)?;
+    let max_load = recorder
+        .frames
+        .iter()
+        .map(|f| total_load(&f.platform, NORTH))
+        .max()
+        .unwrap_or(0);
+
+    for (k, frame) in recorder.frames.iter().enumerate() {
+        let path = dir.join(format!("tilt_{:05}.ppm", k));
+        std::fs::write(path, ppm(frame, tile_size, max_load))?;
+    }
+    Ok(recorder.frames.len())
+}
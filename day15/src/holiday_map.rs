@@ -0,0 +1,127 @@
+use std::borrow::Borrow;
+
+use crate::hash;
+
+const NUM_BOXES: usize = 256;
+
+// The Holiday ASCII String Helper Manual Arrangement Procedure: keys go to the box given by
+// their HASH, and each box keeps its entries in insertion order. Replacing the value of a key
+// keeps its slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HolidayHashMap<K, V> {
+    boxes: Vec<Vec<(K, V)>>,
+}
+
+impl<K, V> Default for HolidayHashMap<K, V> {
+    fn default() -> Self {
+        HolidayHashMap {
+            boxes: Vec::from_iter((0..NUM_BOXES).map(|_| Vec::new())),
+        }
+    }
+}
+
+impl<K: AsRef<str>, V> HolidayHashMap<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn slot<Q: AsRef<str> + PartialEq + ?Sized>(&self, key: &Q) -> (usize, Option<usize>)
+    where
+        K: Borrow<Q>,
+    {
+        let box_num = hash(key.as_ref()) as usize;
+        let slot = self.boxes[box_num]
+            .iter()
+            .position(|(k, _)| k.borrow() == key);
+        (box_num, slot)
+    }
+
+    // Returns the previous value of the key if it was already present.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V>
+    where
+        K: PartialEq,
+    {
+        match self.slot(&key) {
+            (box_num, Some(slot)) => {
+                Some(std::mem::replace(&mut self.boxes[box_num][slot].1, value))
+            }
+            (box_num, None) => {
+                self.boxes[box_num].push((key, value));
+                None
+            }
+        }
+    }
+
+    // Later entries of the box move forward to fill the slot.
+    pub fn remove<Q: AsRef<str> + PartialEq + ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        let (box_num, slot) = self.slot(key);
+        slot.map(|slot| self.boxes[box_num].remove(slot).1)
+    }
+
+    pub fn get<Q: AsRef<str> + PartialEq + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        let (box_num, slot) = self.slot(key);
+        slot.map(|slot| &self.boxes[box_num][slot].1)
+    }
+}
+
+impl<K, V> HolidayHashMap<K, V> {
+    pub fn len(&self) -> usize {
+        self.boxes.iter().map(|b| b.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.boxes.iter().all(|b| b.is_empty())
+    }
+
+    // Entries of each box in slot order, for all boxes including empty ones.
+    pub fn boxes(&self) -> impl Iterator<Item = &[(K, V)]> {
+        self.boxes.iter().map(|b| b.as_slice())
+    }
+
+    // Entries by box then by slot.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.boxes.iter().flatten().map(|(k, v)| (k, v))
+    }
+}
+
+impl<K, V> IntoIterator for HolidayHashMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = std::iter::Flatten<std::vec::IntoIter<Vec<(K, V)>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.boxes.into_iter().flatten()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a HolidayHashMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+impl<K: AsRef<str> + PartialEq, V> FromIterator<(K, V)> for HolidayHashMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = HolidayHashMap::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+impl<K: AsRef<str> + PartialEq, V> Extend<(K, V)> for HolidayHashMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
@@ -1,21 +1,19 @@
-#![feature(ascii_char)]
-use std::collections::HashMap;
-
+use chumsky::error::SimpleReason;
 use chumsky::prelude::*;
 
+use holiday_map::HolidayHashMap;
+
+mod holiday_map;
+mod preimage;
+
 #[derive(Debug)]
 enum Inst {
     Eq(String, u8),
     Dash(String),
 }
 
-#[derive(Debug)]
-struct Lens {
-    value: u8,
-    insert_order: usize,
-}
-
-type Boxes = Vec<HashMap<String, Lens>>;
+// Focal length of the lens with each label.
+type Boxes = HolidayHashMap<String, u8>;
 
 fn instruction_str(inst: &Inst) -> String {
     match inst {
@@ -24,61 +22,96 @@ fn instruction_str(inst: &Inst) -> String {
     }
 }
 
+// Line breaks are ignored wherever they appear, even within a step. Labels are identifiers
+// made of ASCII letters, digits and underscores, not starting with a digit. Other letters are
+// reported as errors since they have no ASCII code to hash.
 fn parse_instruction_list() -> impl Parser<char, Vec<Inst>, Error = Simple<char>> {
-    let scalar = text::int(10).map(|s: String| s.parse().unwrap());
-    let eq = text::ident()
-        .then_ignore(just("="))
+    let line_breaks = one_of("\r\n").repeated();
+    let label_char = |class: fn(&char) -> bool| {
+        filter(move |c: &char| class(c) || !c.is_ascii())
+            .try_map(|c: char, span| {
+                if c.is_ascii() {
+                    Ok(c)
+                } else {
+                    Err(Simple::custom(span, format!("non-ASCII character {:?}", c)))
+                }
+            })
+            .then_ignore(line_breaks.clone())
+    };
+    let label = label_char(|c| c.is_ascii_alphabetic() || *c == '_')
+        .chain(label_char(|c| c.is_ascii_alphanumeric() || *c == '_').repeated())
+        .collect::<String>();
+    let scalar = filter(char::is_ascii_digit)
+        .then_ignore(line_breaks.clone())
+        .repeated()
+        .at_least(1)
+        .collect::<String>()
+        .try_map(|s, span| {
+            s.parse()
+                .map_err(|_| Simple::custom(span, format!("focal length {} out of range", s)))
+        });
+    let eq = label
+        .clone()
+        .then_ignore(just('=').then(line_breaks.clone()))
         .then(scalar)
         .map(|(v, s)| Inst::Eq(v, s));
-    let minus = text::ident().then_ignore(just("-")).map(|v| Inst::Dash(v));
+    let minus = label
+        .then_ignore(just('-').then(line_breaks.clone()))
+        .map(Inst::Dash);
     let inst = eq.or(minus);
-    inst.separated_by(just(","))
+    line_breaks
+        .clone()
+        .ignore_then(inst.separated_by(just(',').then(line_breaks)))
+        .then_ignore(end())
 }
 
-fn hash(str: &String) -> u8 {
-    let mut cur_val = 0_u16;
-
-    for c in str.chars() {
-        cur_val += c.as_ascii().unwrap().to_u8() as u16;
-        cur_val *= 17;
-        cur_val %= 256;
-    }
+// Arithmetic on u8 wraps around modulo 256.
+fn hash_step(state: u8, c: u8) -> u8 {
+    state.wrapping_add(c).wrapping_mul(17)
+}
 
-    cur_val as u8
+// Labels only hold ASCII characters, which the parser checks.
+fn hash(str: &str) -> u8 {
+    str.bytes().fold(0, hash_step)
 }
 
 fn instruction_hash(inst: &Inst) -> u8 {
     hash(&instruction_str(inst))
 }
 
-fn compute_boxes(inst_list: Vec<Inst>) -> Boxes {
-    let mut boxes = Vec::from_iter((0..256).map(|_| HashMap::new()));
-
-    for (i, inst) in inst_list.into_iter().enumerate() {
-        match inst {
-            Inst::Eq(label, value) => {
-                let hash = hash(&label);
-                let lens_box = boxes.get_mut(hash as usize).unwrap();
-                let to_insert = match lens_box.remove(&label) {
-                    None => Lens {
-                        value,
-                        insert_order: i,
-                    },
-                    Some(Lens {
-                        value: _,
-                        insert_order,
-                    }) => Lens {
-                        value,
-                        insert_order,
-                    },
-                };
-                lens_box.insert(label, to_insert);
-            }
-            Inst::Dash(label) => {
-                let hash = hash(&label);
-                let lens_box = boxes.get_mut(hash as usize).unwrap();
-                lens_box.remove(&label);
-            }
+fn apply(boxes: &mut Boxes, inst: Inst) {
+    match inst {
+        Inst::Eq(label, value) => {
+            boxes.insert(label, value);
+        }
+        Inst::Dash(label) => {
+            boxes.remove(&label);
+        }
+    }
+}
+
+// Non-empty boxes with their lenses in slot order, as in the puzzle statement.
+fn render_boxes(boxes: &Boxes) -> String {
+    let mut rendered = String::new();
+
+    for (box_num, lenses) in boxes.boxes().enumerate() {
+        if !lenses.is_empty() {
+            let lenses = Vec::from_iter(lenses.iter().map(|(l, v)| format!("[{} {}]", l, v)));
+            rendered.push_str(&format!("Box {}: {}\n", box_num, lenses.join(" ")));
+        }
+    }
+    rendered
+}
+
+// With `trace`, prints the boxes after each step.
+fn compute_boxes(inst_list: Vec<Inst>, trace: bool) -> Boxes {
+    let mut boxes = HolidayHashMap::new();
+
+    for inst in inst_list {
+        let step = instruction_str(&inst);
+        apply(&mut boxes, inst);
+        if trace {
+            println!("After \"{}\":\n{}", step, render_boxes(&boxes));
         }
     }
     boxes
@@ -86,23 +119,68 @@ fn compute_boxes(inst_list: Vec<Inst>) -> Boxes {
 
 fn focusing_power(boxes: &Boxes) -> usize {
     boxes
-        .into_iter()
+        .boxes()
         .enumerate()
-        .map(|(box_num, lenses)| {
-            let mut lenses = Vec::from_iter((*lenses).values());
-            lenses.sort_by_key(|l| l.insert_order);
+        .flat_map(|(box_num, lenses)| {
             lenses
-                .into_iter()
+                .iter()
                 .enumerate()
-                .map(|(slot, lens)| lens.value as usize * (slot + 1) * (box_num + 1))
-                .sum::<usize>()
+                .map(move |(slot, (_, value))| *value as usize * (slot + 1) * (box_num + 1))
         })
         .sum()
 }
 
+// Line and column, both starting at 1, of a character offset.
+fn line_column(src: &str, offset: usize) -> (usize, usize) {
+    let before = Vec::from_iter(src.chars().take(offset));
+    let line = before.iter().filter(|c| **c == '\n').count() + 1;
+    let column = before.iter().rev().take_while(|c| **c != '\n').count() + 1;
+    (line, column)
+}
+
 fn main() {
-    let src = std::fs::read_to_string(std::env::args().nth(1).unwrap()).unwrap();
-    let instructions = parse_instruction_list().parse(src).unwrap();
+    let args = Vec::from_iter(std::env::args());
+
+    // "preimages <box> <charset> <min length> <max length> [limit]" lists labels going to a box.
+    if args[1] == "preimages" {
+        let target: u8 = args[2].parse().unwrap();
+        let charset = preimage::parse_charset(&args[3]).unwrap();
+        let lengths = args[4].parse().unwrap()..=args[5].parse().unwrap();
+        let limit = args.get(6).map_or(20, |a| a.parse().unwrap());
+        let found = preimage::preimages(target, &charset, lengths, limit);
+        let num_labels = match found.num_labels {
+            u128::MAX => format!("At least {}", u128::MAX),
+            n => n.to_string(),
+        };
+        println!("{} labels go to box {}", num_labels, target);
+        found.labels.iter().for_each(|l| println!("{}", l));
+        return;
+    }
+    let src = std::fs::read_to_string(&args[1]).unwrap();
+    let instructions = match parse_instruction_list().parse(src.as_str()) {
+        Ok(instructions) => instructions,
+        Err(errors) => {
+            for error in errors {
+                let (line, column) = line_column(&src, error.span().start);
+                // The Display implementation of errors leaves custom messages out.
+                let message = match error.reason() {
+                    SimpleReason::Custom(message) => message.clone(),
+                    _ => error.to_string(),
+                };
+                println!("Error at line {} column {}: {}", line, column, message);
+            }
+            std::process::exit(1);
+        }
+    };
+    // "trace" prints the boxes after every step, "collisions" the labels sharing a box.
+    let trace = args.get(2).map(|a| a.as_str()) == Some("trace");
+    if args.get(2).map(|a| a.as_str()) == Some("collisions") {
+        for (box_num, labels) in preimage::collisions(&instructions) {
+            println!("Box {}: {}", box_num, labels.join(" "));
+        }
+        return;
+    }
+
     println!(
         "Question 1 answer is: {}",
         instructions
@@ -112,6 +190,6 @@ fn main() {
     );
     println!(
         "Question 2 answer is: {}",
-        focusing_power(&compute_boxes(instructions))
+        focusing_power(&compute_boxes(instructions, trace))
     );
 }
@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+
+use crate::{hash, hash_step, Inst};
+
+// 17 * 241 = 16 * 256 + 1, so multiplying by 241 undoes the multiplication of a HASH step.
+const INVERSE_17: u8 = 241;
+
+// State before the character that leads to the given state.
+pub fn unhash_step(state: u8, c: u8) -> u8 {
+    state.wrapping_mul(INVERSE_17).wrapping_sub(c)
+}
+
+// Character set given by single characters and ranges such as "a-z0-9_".
+pub fn parse_charset(spec: &str) -> Result<Vec<u8>, String> {
+    if !spec.is_ascii() {
+        return Err(format!("non-ASCII character set {}", spec));
+    }
+    let spec = spec.as_bytes();
+    let mut charset = Vec::new();
+    let mut k = 0;
+
+    while k < spec.len() {
+        if k + 2 < spec.len() && spec[k + 1] == b'-' {
+            if spec[k] > spec[k + 2] {
+                return Err(format!(
+                    "empty range {}-{}",
+                    spec[k] as char,
+                    spec[k + 2] as char
+                ));
+            }
+            charset.extend(spec[k]..=spec[k + 2]);
+            k += 3;
+        } else {
+            charset.push(spec[k]);
+            k += 1;
+        }
+    }
+    charset.sort();
+    charset.dedup();
+    Ok(charset)
+}
+
+// Number of labels with characters from the set leading to the target box, as
+// counts[k][state] for labels of k characters read from the given state. Built backwards from
+// the target by undoing one step at a time, saturating at u128::MAX.
+fn counts_to_target(target: u8, charset: &[u8], max_len: usize) -> Vec<[u128; 256]> {
+    let mut counts = vec![[0_u128; 256]; max_len + 1];
+    counts[0][target as usize] = 1;
+
+    for k in 1..=max_len {
+        for state in 0..=255 {
+            let count = counts[k - 1][state as usize];
+            if count > 0 {
+                for c in charset {
+                    let before = &mut counts[k][unhash_step(state, *c) as usize];
+                    *before = before.saturating_add(count);
+                }
+            }
+        }
+    }
+    counts
+}
+
+// Number of labels hashing to the target box and the first few of them.
+pub struct Preimages {
+    pub num_labels: u128,
+    pub labels: Vec<String>,
+}
+
+// Labels hashing to the target box, shortest first then in character set order. Labels are
+// never empty, so lengths start at 1 at the least. The counts prune every prefix that cannot be
+// completed, so each label found costs at most `len` steps.
+pub fn preimages(
+    target: u8,
+    charset: &[u8],
+    lengths: std::ops::RangeInclusive<usize>,
+    limit: usize,
+) -> Preimages {
+    let lengths = (*lengths.start()).max(1)..=*lengths.end();
+    let counts = counts_to_target(target, charset, *lengths.end());
+    let mut labels = Vec::new();
+
+    fn extend(
+        counts: &[[u128; 256]],
+        charset: &[u8],
+        state: u8,
+        label: &mut Vec<u8>,
+        remaining: usize,
+        limit: usize,
+        labels: &mut Vec<String>,
+    ) {
+        if remaining == 0 {
+            if counts[0][state as usize] > 0 {
+                labels.push(String::from_utf8(label.clone()).unwrap());
+            }
+            return;
+        }
+        for c in charset {
+            let next = hash_step(state, *c);
+            if labels.len() < limit && counts[remaining - 1][next as usize] > 0 {
+                label.push(*c);
+                extend(counts, charset, next, label, remaining - 1, limit, labels);
+                label.pop();
+            }
+        }
+    }
+    for len in lengths.clone().filter(|len| counts[*len][0] > 0) {
+        if labels.len() >= limit {
+            break;
+        }
+        extend(
+            &counts,
+            charset,
+            0,
+            &mut Vec::new(),
+            len,
+            limit,
+            &mut labels,
+        );
+    }
+
+    Preimages {
+        num_labels: lengths.fold(0_u128, |n, len| n.saturating_add(counts[len][0])),
+        labels,
+    }
+}
+
+// Boxes holding more than one distinct label of the instructions, with those labels in order
+// of first appearance.
+pub fn collisions(instructions: &[Inst]) -> BTreeMap<u8, Vec<&str>> {
+    let mut boxes: BTreeMap<u8, Vec<&str>> = BTreeMap::new();
+
+    for inst in instructions {
+        let label = match inst {
+            Inst::Eq(label, _) | Inst::Dash(label) => label.as_str(),
+        };
+        let labels = boxes.entry(hash(label)).or_default();
+        if !labels.contains(&label) {
+            labels.push(label);
+        }
+    }
+    boxes.retain(|_, labels| labels.len() > 1);
+    boxes
+}
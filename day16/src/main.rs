@@ -3,14 +3,19 @@ use rayon::prelude::*;
 use std::collections::{BTreeMap, HashSet};
 use std::ops::{Deref, Range};
 
+use chumsky::error::SimpleReason;
 use chumsky::prelude::*;
 
+mod segments;
+mod tiles;
+
+use tiles::{TileRegistry, DIRECTIONS};
+
 type Scalar = i32;
 
 type Vector = [Scalar; 2];
 
-// We only care about 90/270 degree rotation so
-// we just store the sine.
+// Number of quarter turns to the left.
 type Rotation = Scalar;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, PartialOrd, Ord, Copy)]
@@ -23,40 +28,67 @@ struct Beam {
 // applied to the input beam. Because we have
 // separate storage for horizontal and vertical
 // beams, mirrors will be stored with different
-// matrices.
-type Obstacle = Vec<Rotation>;
+// matrices. Each line stores the rotations for
+// beams going backwards then forwards along it.
+type Obstacle = [Vec<Rotation>; 2];
 
 struct Map {
     row_col_obstacles: [Vec<BTreeMap<Scalar, Obstacle>>; 2],
     shape: [usize; 2],
 }
 
+const ROT_NONE: Rotation = 0;
 const ROT_LEFT: Rotation = 1;
+const ROT_BACK: Rotation = 2;
 const ROT_RIGHT: Rotation = -1;
 
+// Obstacles of a row with their positions, and the row length.
+type ObstacleRow = (Vec<([Option<Obstacle>; 2], Vector)>, usize);
+
+// Obstacles of a tile for horizontal then vertical beams. Lines a tile lets every beam through
+// unchanged get no obstacle.
+fn tile_obstacles(rotations: &tiles::TileRotations) -> [Option<Obstacle>; 2] {
+    let mut obstacles: [Obstacle; 2] = Default::default();
+    for (dir, rot) in DIRECTIONS.iter().zip(rotations) {
+        let beam_dim: usize = if dir[0] == 0 { 0 } else { 1 };
+        let forwards = (dir[1 - beam_dim] > 0) as usize;
+        obstacles[beam_dim][forwards] = rot.clone();
+    }
+    obstacles.map(|o| {
+        if o.iter().all(|rot| *rot == [ROT_NONE]) {
+            None
+        } else {
+            Some(o)
+        }
+    })
+}
+
 fn parse_obstacles(
-) -> impl Parser<char, Vec<(Vec<([Obstacle; 2], Vector)>, usize)>, Error = Simple<char>> {
+    registry: &TileRegistry,
+) -> impl Parser<char, Vec<ObstacleRow>, Error = Simple<char>> + '_ {
+    // Unknown tiles are reported without stopping the row, so that the error is not superseded
+    // by the one about the rest of the input.
     let obstacle =
-        one_of(r"\/|-.").map_with_span(|c: char, span: Range<usize>| -> ([Obstacle; 2], usize) {
-            (
-                match c {
-                    '|' => [vec![ROT_LEFT, ROT_RIGHT], vec![]],
-                    '-' => [vec![], vec![ROT_LEFT, ROT_RIGHT]],
-                    '/' => [vec![ROT_LEFT], vec![ROT_RIGHT]],
-                    '\\' => [vec![ROT_RIGHT], vec![ROT_LEFT]],
-                    _ => [vec![], vec![]],
-                },
-                span.start,
-            )
+        filter(|c: &char| !c.is_whitespace()).validate(|c: char, span: Range<usize>, emit| {
+            match registry.get(c) {
+                Some(rotations) => (tile_obstacles(rotations), span.start),
+                None => {
+                    emit(Simple::custom(
+                        span.clone(),
+                        format!("unknown tile {:?}", c),
+                    ));
+                    ([None, None], span.start)
+                }
+            }
         });
     let obstacle_row = obstacle.repeated().at_least(1).map_with_span(
-        |obstacles, row_span: Range<usize>| -> (Vec<([Obstacle; 2], Vector)>, usize) {
+        |obstacles, row_span: Range<usize>| -> ObstacleRow {
             let row_length = row_span.end - row_span.start;
             (
                 obstacles
                     .into_iter()
                     .filter_map(|(row_col_obst, pos)| {
-                        if row_col_obst.iter().all(|o| o.len() == 0) {
+                        if row_col_obst.iter().all(|o| o.is_none()) {
                             None
                         } else {
                             Some((
@@ -73,22 +105,33 @@ fn parse_obstacles(
             )
         },
     );
-    obstacle_row.separated_by(text::newline())
+    obstacle_row
+        .separated_by(text::newline())
+        .then_ignore(text::whitespace())
+        .then_ignore(end())
+}
+
+// Custom errors such as unknown tiles only show their message, which Display leaves out.
+fn error_message(error: &Simple<char>) -> String {
+    match error.reason() {
+        SimpleReason::Custom(message) => message.clone(),
+        _ => error.to_string(),
+    }
 }
 
-fn obstacles_to_map(obstacles: Vec<(Vec<([Obstacle; 2], Vector)>, usize)>) -> Map {
+fn obstacles_to_map(obstacles: Vec<ObstacleRow>) -> Map {
     let num_rows = obstacles.len();
     let num_cols = obstacles[0].1;
     let mut row_col_obstacles = [
         Vec::from_iter((0..num_rows).map(|_| BTreeMap::new())),
-        Vec::from_iter((0..num_rows).map(|_| BTreeMap::new())),
+        Vec::from_iter((0..num_cols).map(|_| BTreeMap::new())),
     ];
 
-    for (obsts, pos) in obstacles.iter().map(|r| r.0.deref()).flatten() {
+    for (obsts, pos) in obstacles.iter().flat_map(|r| r.0.deref()) {
         for dim1 in 0_usize..2 {
-            if obsts[dim1].len() > 0 {
+            if let Some(obst) = &obsts[dim1] {
                 let dim2 = 1 - dim1;
-                row_col_obstacles[dim1][pos[dim1] as usize].insert(pos[dim2], obsts[dim1].clone());
+                row_col_obstacles[dim1][pos[dim1] as usize].insert(pos[dim2], obst.clone());
             }
         }
     }
@@ -99,82 +142,121 @@ fn obstacles_to_map(obstacles: Vec<(Vec<([Obstacle; 2], Vector)>, usize)>) -> Ma
     }
 }
 
-fn rotate([x, y]: Vector, rot: Rotation) -> Vector {
-    [-rot * y, rot * x]
+fn rotate(dir: Vector, rot: Rotation) -> Vector {
+    (0..rot.rem_euclid(4)).fold(dir, |[x, y], _| [-y, x])
 }
 
-fn simulate(map: &Map, beams: HashSet<Beam>) -> HashSet<Vector> {
-    let mut energized = HashSet::new();
-    let mut beams: HashSet<Beam> = beams;
-    let mut traversed_states: HashSet<Vec<Beam>> = HashSet::new();
-
-    while beams.len() > 0 {
-        let mut sorted_beams: Vec<Beam> = beams.iter().map(|t| *t).collect();
-        sorted_beams.sort();
-        if traversed_states.contains(&sorted_beams) {
-            break;
-        }
-        traversed_states.insert(sorted_beams);
-
-        for beam in beams.clone() {
-            // We remove the current beam no matter what.
-            beams.remove(&beam);
-            let beam_dim: usize = if beam.dir[0] == 0 { 0 } else { 1 };
-            let beam_orient = beam.dir[1 - beam_dim];
-            // Naming assuming horizontal beam, beam_dim = 0
-            let beam_row = beam.pos[beam_dim] as usize;
-            let beam_col = beam.pos[1 - beam_dim];
-            // Looking up the next obstacle if any
-            let opt_obst = if beam_orient > 0 {
-                map.row_col_obstacles[beam_dim][beam_row]
-                    .lower_bound(std::ops::Bound::Excluded(&beam_col))
+// Tiles a beam goes through up to its next obstacle, and the beams that obstacle sends out.
+fn trace_beam(map: &Map, beam: &Beam) -> (Vec<Vector>, Vec<Beam>) {
+    let mut tiles = Vec::new();
+    let mut new_beams = Vec::new();
+
+    let beam_dim: usize = if beam.dir[0] == 0 { 0 } else { 1 };
+    let beam_orient = beam.dir[1 - beam_dim];
+    // Naming assuming horizontal beam, beam_dim = 0
+    let beam_row = beam.pos[beam_dim] as usize;
+    let beam_col = beam.pos[1 - beam_dim];
+    // Looking up the next obstacle if any
+    let opt_obst = if beam_orient > 0 {
+        map.row_col_obstacles[beam_dim][beam_row]
+            .lower_bound(std::ops::Bound::Excluded(&beam_col))
+            .peek_next()
+    } else {
+        map.row_col_obstacles[beam_dim][beam_row]
+            .upper_bound(std::ops::Bound::Excluded(&beam_col))
+            .peek_prev()
+    };
+    // Creating new rotated/split beams as necessary while getting the last
+    // column of the current beam.
+    let last_col = match opt_obst {
+        None => {
+            // No obstacle in that direction. We finished simulating
+            // this beam, so we don't add a new one.
+            if beam_orient > 0 {
+                map.shape[1 - beam_dim] as i32 - 1
             } else {
-                map.row_col_obstacles[beam_dim][beam_row]
-                    .upper_bound(std::ops::Bound::Excluded(&beam_col))
+                0
             }
-            .key_value();
-            // Creating new rotated/split beams as necessary while getting the last
-            // column of the current beam.
-            let last_col = match opt_obst {
-                None => {
-                    // No obstacle in that direction. We finished simulating
-                    // this beam, so we don't add a new one.
-                    if beam_orient > 0 {
-                        map.shape[1 - beam_dim] as i32 - 1
-                    } else {
-                        0
-                    }
-                }
-                Some((new_col, obsts)) => {
-                    // We reached an obstacle, so we create new beams
-                    // according to the obstacle rotations.
-                    for obst in obsts {
-                        let mut new_beam = beam.clone();
-                        new_beam.pos[1 - beam_dim] = *new_col;
-                        new_beam.dir = rotate(beam.dir, *obst);
-                        beams.insert(new_beam);
-                    }
-                    *new_col
-                }
-            };
-            // Adding energized locations between the current and last location.
-            let mut col_range = [beam_col, last_col];
-            col_range.sort();
-
-            for col in col_range[0]..=col_range[1] {
-                let mut en_pos = beam.pos;
-                en_pos[1 - beam_dim] = col;
-                energized.insert(en_pos);
+        }
+        Some((new_col, obsts)) => {
+            // We reached an obstacle, so we create new beams
+            // according to the obstacle rotations.
+            for obst in &obsts[(beam_orient > 0) as usize] {
+                let mut new_beam = *beam;
+                new_beam.pos[1 - beam_dim] = *new_col;
+                new_beam.dir = rotate(beam.dir, *obst);
+                new_beams.push(new_beam);
             }
+            *new_col
         }
+    };
+    // Adding energized locations between the current and last location.
+    let mut col_range = [beam_col, last_col];
+    col_range.sort();
+
+    for col in col_range[0]..=col_range[1] {
+        let mut en_pos = beam.pos;
+        en_pos[1 - beam_dim] = col;
+        tiles.push(en_pos);
     }
+    (tiles, new_beams)
+}
+
+// Moves every beam to its next obstacle, marking the tiles on the way as energized. Beams are
+// kept sorted so that equal beam sets are equal states.
+fn advance(map: &Map, beams: &[Beam], energized: &mut HashSet<Vector>) -> Vec<Beam> {
+    let mut new_beams = Vec::new();
+
+    for beam in beams {
+        let (tiles, emitted) = trace_beam(map, beam);
+        energized.extend(tiles);
+        new_beams.extend(emitted);
+    }
+    new_beams.sort();
+    new_beams.dedup();
+    new_beams
+}
+
+// Once no beam is left the state no longer changes, so the simulation always ends in a cycle.
+fn simulate(map: &Map, beams: HashSet<Beam>) -> HashSet<Vector> {
+    let mut energized = HashSet::new();
+    let mut beams = Vec::from_iter(beams);
+    beams.sort();
+    cycle::hashing(beams, |beams| advance(map, beams, &mut energized));
 
     energized
 }
 
 fn main() {
-    let src = std::fs::read_to_string(std::env::args().nth(1).unwrap()).unwrap();
-    let map = &obstacles_to_map(parse_obstacles().parse(src).unwrap());
+    let args = Vec::from_iter(std::env::args().filter(|a| !a.starts_with("--")));
+    let src = std::fs::read_to_string(&args[1]).unwrap();
+
+    // "--extended" adds absorbers, diodes and three way splitters to the puzzle tiles, and
+    // "--tiles=<file>" defines more tiles.
+    let mut registry = if std::env::args().any(|a| a == "--extended") {
+        TileRegistry::extended()
+    } else {
+        TileRegistry::standard()
+    };
+    for path in std::env::args().filter_map(|a| a.strip_prefix("--tiles=").map(String::from)) {
+        let definitions = std::fs::read_to_string(path).unwrap();
+        for (symbol, rotations) in tiles::parse_definitions(&definitions).unwrap() {
+            registry.register(symbol, rotations);
+        }
+    }
+    let map = &match parse_obstacles(&registry).parse(src) {
+        Ok(obstacles) => obstacles_to_map(obstacles),
+        Err(errors) => {
+            for error in errors {
+                println!(
+                    "Error at character {}: {}",
+                    error.span().start,
+                    error_message(&error)
+                );
+            }
+            std::process::exit(1);
+        }
+    };
     let energized = simulate(
         map,
         HashSet::from([Beam {
@@ -184,32 +266,32 @@ fn main() {
     );
 
     println!("Question 1 answer is: {}", energized.len());
-    let shape = map.shape.map(|i| i as Scalar - 1);
-    let end_points = [[0, 0], [0, shape[1]], [shape[0], 0], shape];
-    let edges = [
-        (0, 1, [1, 0]),
-        (0, 2, [0, 1]),
-        (1, 3, [0, -1]),
-        (2, 3, [-1, 0]),
-    ];
-
-    println!(
-        "Question 2 answer is: {}",
-        edges
-            .into_iter()
-            .flat_map(|(ix1, ix2, dir)| {
-                let p1 @ [i1, _] = end_points[ix1];
-                let p2 @ [i2, _] = end_points[ix2];
-                let dim = if i1 < i2 { 0 } else { 1 };
-                (p1[dim]..p2[dim]).map(move |i| {
-                    let mut pos = p1;
-                    pos[dim] = i;
-                    HashSet::from([Beam { pos, dir }])
-                })
-            })
-            .par_bridge()
-            .map(|beams| simulate(map, beams).len())
-            .max()
-            .unwrap()
+    // Beams entering from every edge tile, rows then columns.
+    let [num_rows, num_cols] = map.shape.map(|n| n as Scalar);
+    let entries = Vec::from_iter(
+        (0..num_rows)
+            .flat_map(|i| [([i, 0], [0, 1]), ([i, num_cols - 1], [0, -1])])
+            .chain((0..num_cols).flat_map(|j| [([0, j], [1, 0]), ([num_rows - 1, j], [-1, 0])]))
+            .map(|(pos, dir)| Beam { pos, dir }),
     );
+    let graph = segments::SegmentGraph::new(map, &entries);
+    let counts = Vec::from_iter(entries.iter().map(|e| graph.num_energized(e).unwrap()));
+
+    // Checking every entry against a full simulation is opt-in.
+    if std::env::args().any(|a| a == "--verify") {
+        let num_mismatches = entries
+            .par_iter()
+            .zip(counts.par_iter())
+            .filter(|(entry, count)| simulate(map, HashSet::from([**entry])).len() != **count)
+            .count();
+        println!(
+            "Verified {} entries over {} segments in {} components, {} mismatches",
+            entries.len(),
+            graph.num_segments(),
+            graph.num_components(),
+            num_mismatches
+        );
+    }
+
+    println!("Question 2 answer is: {}", counts.iter().max().unwrap());
 }
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use petgraph::algo::tarjan_scc;
+use petgraph::graph::{DiGraph, NodeIndex};
+
+use crate::{trace_beam, Beam, Map};
+
+// Set of tiles as a bitset in row major order.
+type Tiles = Vec<u64>;
+
+// Every beam the contraption can produce from the given entry beams, linked to the beams sent
+// out by the obstacle ending its segment. Beams of a strongly connected component energize
+// the same tiles, so those are computed once per component from the ones of the components
+// downstream.
+pub struct SegmentGraph {
+    nodes: HashMap<Beam, NodeIndex>,
+    component_of: Vec<usize>,
+    energized: Vec<Tiles>,
+}
+
+impl SegmentGraph {
+    pub fn new(map: &Map, entries: &[Beam]) -> SegmentGraph {
+        let num_cols = map.shape[1];
+        let num_words = (map.shape[0] * num_cols).div_ceil(64);
+        let mut graph: DiGraph<Tiles, ()> = DiGraph::new();
+        let mut nodes: HashMap<Beam, NodeIndex> = HashMap::new();
+        let mut to_trace = Vec::new();
+        let mut node_of = |beam: Beam, graph: &mut DiGraph<Tiles, ()>, to_trace: &mut Vec<_>| {
+            *nodes.entry(beam).or_insert_with(|| {
+                to_trace.push(beam);
+                graph.add_node(Vec::new())
+            })
+        };
+
+        entries.iter().for_each(|b| {
+            node_of(*b, &mut graph, &mut to_trace);
+        });
+        while let Some(beam) = to_trace.pop() {
+            let (tiles, emitted) = trace_beam(map, &beam);
+            let mut segment = vec![0; num_words];
+            for [i, j] in tiles {
+                let k = i as usize * num_cols + j as usize;
+                segment[k / 64] |= 1 << (k % 64);
+            }
+            let node = node_of(beam, &mut graph, &mut to_trace);
+            graph[node] = segment;
+            for next in emitted {
+                let next_node = node_of(next, &mut graph, &mut to_trace);
+                graph.add_edge(node, next_node, ());
+            }
+        }
+
+        // Components come out of Tarjan's algorithm downstream first.
+        let components = tarjan_scc(&graph);
+        let mut component_of = vec![0; graph.node_count()];
+        let mut energized: Vec<Tiles> = Vec::with_capacity(components.len());
+        for (c, members) in components.iter().enumerate() {
+            let mut tiles = vec![0; num_words];
+            for node in members {
+                component_of[node.index()] = c;
+            }
+            for node in members {
+                let downstream = graph
+                    .neighbors(*node)
+                    .map(|n| component_of[n.index()])
+                    .filter(|d| *d != c);
+                for source in
+                    std::iter::once(&graph[*node]).chain(downstream.map(|d| &energized[d]))
+                {
+                    tiles.iter_mut().zip(source).for_each(|(t, s)| *t |= s);
+                }
+            }
+            energized.push(tiles);
+        }
+
+        SegmentGraph {
+            nodes,
+            component_of,
+            energized,
+        }
+    }
+
+    pub fn num_energized(&self, entry: &Beam) -> Option<usize> {
+        let node = self.nodes.get(entry)?;
+        let tiles = &self.energized[self.component_of[node.index()]];
+        Some(tiles.iter().map(|w| w.count_ones() as usize).sum())
+    }
+
+    pub fn num_segments(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn num_components(&self) -> usize {
+        self.energized.len()
+    }
+}
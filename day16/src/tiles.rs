@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use crate::{Rotation, Vector, ROT_BACK, ROT_LEFT, ROT_NONE, ROT_RIGHT};
+
+// Directions of travel in the order used by tile definitions: east, south, west then north.
+pub const DIRECTIONS: [Vector; 4] = [[0, 1], [1, 0], [0, -1], [-1, 0]];
+
+// Rotations applied to a beam entering the tile, for each direction of travel. A beam with no
+// rotation is absorbed, a single ROT_NONE lets it through unchanged.
+pub type TileRotations = [Vec<Rotation>; 4];
+
+#[derive(Debug, Clone)]
+pub struct TileRegistry {
+    tiles: HashMap<char, TileRotations>,
+}
+
+impl TileRegistry {
+    // Empty space, splitters and mirrors of the puzzle.
+    pub fn standard() -> TileRegistry {
+        let pass = || vec![ROT_NONE];
+        let split = || vec![ROT_LEFT, ROT_RIGHT];
+        let mut registry = TileRegistry {
+            tiles: HashMap::new(),
+        };
+        registry.register('.', [pass(), pass(), pass(), pass()]);
+        registry.register('|', [split(), pass(), split(), pass()]);
+        registry.register('-', [pass(), split(), pass(), split()]);
+        registry.register(
+            '/',
+            [
+                vec![ROT_LEFT],
+                vec![ROT_RIGHT],
+                vec![ROT_LEFT],
+                vec![ROT_RIGHT],
+            ],
+        );
+        registry.register(
+            '\\',
+            [
+                vec![ROT_RIGHT],
+                vec![ROT_LEFT],
+                vec![ROT_RIGHT],
+                vec![ROT_LEFT],
+            ],
+        );
+        registry
+    }
+
+    // Standard tiles plus an absorber '#', a splitter '+' sending beams left, right and
+    // straight on, and diodes '>', 'v', '<' and '^' which absorb beams going against the arrow
+    // and let all others through.
+    pub fn extended() -> TileRegistry {
+        let mut registry = TileRegistry::standard();
+        registry.register('#', [vec![], vec![], vec![], vec![]]);
+        registry.register('+', [(); 4].map(|_| vec![ROT_LEFT, ROT_NONE, ROT_RIGHT]));
+        for (arrow, d) in ['>', 'v', '<', '^'].into_iter().zip(0..4) {
+            let mut rotations = [(); 4].map(|_| vec![ROT_NONE]);
+            rotations[(d + 2) % 4] = vec![];
+            registry.register(arrow, rotations);
+        }
+        registry
+    }
+
+    // Adds a tile type or replaces the existing one with the same symbol.
+    pub fn register(&mut self, symbol: char, rotations: TileRotations) {
+        self.tiles.insert(symbol, rotations);
+    }
+
+    pub fn get(&self, symbol: char) -> Option<&TileRotations> {
+        self.tiles.get(&symbol)
+    }
+}
+
+// Tile definitions, one per line, as the symbol followed by the turns of beams travelling east,
+// south, west and north. Turns are L, R, S for straight on and B for back, a beam absorbed
+// having turns written x. For instance "+ LSR LSR LSR LSR".
+pub fn parse_definitions(src: &str) -> Result<Vec<(char, TileRotations)>, String> {
+    let parse_turns = |turns: &str| -> Result<Vec<Rotation>, String> {
+        if turns == "x" {
+            return Ok(Vec::new());
+        }
+        turns
+            .chars()
+            .map(|t| match t {
+                'L' => Ok(ROT_LEFT),
+                'R' => Ok(ROT_RIGHT),
+                'S' => Ok(ROT_NONE),
+                'B' => Ok(ROT_BACK),
+                _ => Err(format!("unknown turn {}", t)),
+            })
+            .collect()
+    };
+
+    src.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|line| {
+            let fields = Vec::from_iter(line.split_whitespace());
+            let [symbol, turns @ ..] = &fields[..] else {
+                unreachable!()
+            };
+            let mut symbol_chars = symbol.chars();
+            let (Some(symbol), None, 4) = (symbol_chars.next(), symbol_chars.next(), turns.len())
+            else {
+                return Err(format!("invalid tile definition {}", line));
+            };
+            let rotations = turns
+                .iter()
+                .map(|t| parse_turns(t))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((symbol, rotations.try_into().unwrap()))
+        })
+        .collect()
+}
@@ -0,0 +1,172 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use itertools::Itertools;
+use petgraph::algo::tarjan_scc;
+use petgraph::dot::{Config, Dot};
+use petgraph::prelude::*;
+
+use crate::{find_cycle, node_transition, start_nodes, Direction, Network, NodeSet};
+
+type State<'a> = (usize, &'a str);
+
+fn node_graph(network: &Network) -> DiGraph<&str, Direction> {
+    let mut graph = Graph::new();
+    let mut name_to_node: HashMap<&str, NodeIndex> = HashMap::new();
+
+    for name in network.nodes.keys().sorted() {
+        name_to_node.insert(name, graph.add_node(name.as_str()));
+    }
+    for (name, node) in network.nodes.iter() {
+        for (child, direction) in node.children.iter().zip([Direction::L, Direction::R]) {
+            graph.add_edge(
+                name_to_node[name.as_str()],
+                name_to_node[child.as_str()],
+                direction,
+            );
+        }
+    }
+    graph
+}
+
+// All (instruction index, node) states reachable from the start nodes, with their transitions.
+fn state_graph<'a>(network: &'a Network, starts: &[&'a str]) -> DiGraph<State<'a>, ()> {
+    let mut graph = Graph::new();
+    let mut state_to_node: HashMap<State, NodeIndex> = HashMap::new();
+    let mut to_visit: VecDeque<State> = VecDeque::new();
+
+    for start in starts {
+        let state = (0, *start);
+        state_to_node.insert(state, graph.add_node(state));
+        to_visit.push_back(state);
+    }
+    while let Some(state @ (inst_id, name)) = to_visit.pop_front() {
+        let next_name = node_transition(network, name, network.instructions[inst_id]);
+        let next_state = ((inst_id + 1) % network.instructions.len(), next_name);
+        let next_node = *state_to_node.entry(next_state).or_insert_with(|| {
+            to_visit.push_back(next_state);
+            graph.add_node(next_state)
+        });
+        graph.add_edge(state_to_node[&state], next_node, ());
+    }
+    graph
+}
+
+fn unreachable_nodes<'a>(network: &'a Network, starts: &[&'a str]) -> Vec<&'a str> {
+    let mut visited: HashSet<&str> = HashSet::from_iter(starts.iter().copied());
+    let mut to_visit: VecDeque<&str> = VecDeque::from_iter(starts.iter().copied());
+
+    while let Some(name) = to_visit.pop_front() {
+        for child in network.nodes[name].children.iter() {
+            if visited.insert(child) {
+                to_visit.push_back(child);
+            }
+        }
+    }
+    network
+        .nodes
+        .keys()
+        .filter(|name| !visited.contains(name.as_str()))
+        .map(|name| name.as_str())
+        .sorted()
+        .collect()
+}
+
+// The graph of (instruction index, node) states is written in DOT format when a path is given.
+pub fn analyze(network: &Network, starts: &NodeSet, goals: &NodeSet, dot_path: Option<&str>) {
+    let graph = node_graph(network);
+    let start_names = start_nodes(network, starts);
+
+    println!(
+        "Network has {} nodes and {} instructions",
+        network.nodes.len(),
+        network.instructions.len()
+    );
+    let components = Vec::from_iter(
+        tarjan_scc(&graph)
+            .into_iter()
+            .map(|c| Vec::from_iter(c.into_iter().map(|n| graph[n]).sorted())),
+    );
+    let nontrivial = Vec::from_iter(components.iter().filter(|c| c.len() > 1));
+    println!(
+        "Strongly connected components: {} ({} with more than one node)",
+        components.len(),
+        nontrivial.len()
+    );
+    for component in nontrivial {
+        println!("  {} nodes: {}", component.len(), component.join(" "));
+    }
+
+    let self_loops = Vec::from_iter(graph.edge_references().filter_map(|e| {
+        if e.source() == e.target() {
+            Some(format!("{}({:?})", graph[e.source()], e.weight()))
+        } else {
+            None
+        }
+    }));
+    println!("Self loops: {}", self_loops.join(" "));
+    println!(
+        "Nodes unreachable from the start nodes: {}",
+        unreachable_nodes(network, &start_names).join(" ")
+    );
+
+    for start in start_names.iter() {
+        let ghost = find_cycle(network, start, goals);
+        let mut states: Vec<State> = Vec::new();
+        let mut name = *start;
+        for step in 0..ghost.cycle.num_distinct_states() {
+            let inst_id = (step % network.instructions.len() as u64) as usize;
+            states.push((inst_id, name));
+            name = node_transition(network, name, network.instructions[inst_id]);
+        }
+        let goal_states = |steps: &[u64]| {
+            steps
+                .iter()
+                .map(|t| {
+                    let (inst_id, name) = states[*t as usize];
+                    format!("{}@({}, {})", t, inst_id, name)
+                })
+                .join(" ")
+        };
+        println!(
+            "Start {}: enters its cycle after {} steps, cycle length {}",
+            start, ghost.cycle.prefix_len, ghost.cycle.period
+        );
+        println!(
+            "  goals before the cycle: {}",
+            goal_states(&ghost.prefix_goal_steps)
+        );
+        println!(
+            "  goals within the cycle: {}",
+            goal_states(&ghost.cycle_goal_steps)
+        );
+    }
+
+    let states = state_graph(network, &start_names);
+    let Some(dot_path) = dot_path else {
+        println!("State graph has {} states", states.node_count());
+        return;
+    };
+    let node_attrs = |_, (_, state): (NodeIndex, &State)| {
+        let (inst_id, name) = state;
+        let shape = if goals.contains(name) {
+            "doublecircle"
+        } else if *inst_id == 0 && start_names.contains(name) {
+            "box"
+        } else {
+            "ellipse"
+        };
+        format!("label=\"{} {}\" shape={}", inst_id, name, shape)
+    };
+    let dot = Dot::with_attr_getters(
+        &states,
+        &[Config::EdgeNoLabel, Config::NodeNoLabel],
+        &|_, _| String::new(),
+        &node_attrs,
+    );
+    std::fs::write(dot_path, format!("{:?}", dot)).unwrap();
+    println!(
+        "Wrote state graph with {} states to {}",
+        states.node_count(),
+        dot_path
+    );
+}
@@ -1,8 +1,13 @@
 use chumsky::prelude::*;
-use num::integer::lcm;
-use std::collections::HashMap;
+use cycle::Cycle;
+use itertools::Itertools;
+use num::integer::{ExtendedGcd, Integer};
+use regex::Regex;
+use std::collections::{HashMap, HashSet, VecDeque};
 
-type NodeName = [char; 3];
+mod analysis;
+
+type NodeName = String;
 
 #[derive(Debug, Clone)]
 struct Node {
@@ -34,16 +39,14 @@ fn parser() -> impl Parser<char, Network, Error = Simple<char>> {
         'R' => Direction::R,
         _ => panic!("This should never happen"),
     });
-    let node_name = one_of(String::from_iter(('A'..='Z').chain('0'..='9')))
+    let node_name = filter(|c: &char| c.is_ascii_alphanumeric())
         .repeated()
-        .exactly(3)
-        .map(|s| s.try_into().unwrap());
+        .at_least(1)
+        .collect::<String>();
     let node = node_name
-        .clone()
         .then_ignore(just("=").padded())
         .then(
             node_name
-                .clone()
                 .separated_by(just(",").padded())
                 .exactly(2)
                 .delimited_by(just("("), just(")")),
@@ -61,100 +64,292 @@ fn parser() -> impl Parser<char, Network, Error = Simple<char>> {
         .repeated()
         .at_least(1)
         .then_ignore(text::whitespace())
-        .then(node.clone().separated_by(text::newline()))
+        .then(node.separated_by(text::newline()))
         .map(|(instructions, nodes)| Network {
-            instructions: instructions,
+            instructions,
             nodes: HashMap::from_iter(nodes.iter().map(|t| (*t).clone())),
         })
 }
 
-fn node_transition(network: &Network, from: NodeName, direction: Direction) -> NodeName {
-    network.nodes[&from].children[dir_to_index(&direction)]
+// Start and goal nodes, either matched by a regex on the node name or listed explicitly.
+#[derive(Debug, Clone)]
+enum NodeSet {
+    Pattern(Regex),
+    Names(HashSet<NodeName>),
 }
 
-fn follow_until(network: &Network, start: NodeName, target: NodeName) -> u64 {
-    let mut cur_node_name = start;
-    let mut num_steps = 0_u64;
+impl NodeSet {
+    // Parses "re:<regex>" as a pattern, anything else as a comma separated list of names.
+    pub fn from_spec(spec: &str) -> Result<NodeSet, regex::Error> {
+        match spec.strip_prefix("re:") {
+            Some(pattern) => Ok(NodeSet::Pattern(Regex::new(pattern)?)),
+            None => Ok(NodeSet::Names(HashSet::from_iter(
+                spec.split(',').map(|name| name.trim().to_string()),
+            ))),
+        }
+    }
 
-    for d in network.instructions.iter().cycle() {
-        if cur_node_name == target {
-            break;
-        } else {
-            cur_node_name = node_transition(network, cur_node_name, *d);
+    pub fn contains(&self, name: &str) -> bool {
+        match self {
+            NodeSet::Pattern(regex) => regex.is_match(name),
+            NodeSet::Names(names) => names.contains(name),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum WalkError {
+    UnknownNode(NodeName),
+    NoStartNode,
+    Unreachable(NodeName),
+}
+
+impl std::fmt::Display for WalkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WalkError::UnknownNode(name) => write!(f, "node {} is not in the network", name),
+            WalkError::NoStartNode => write!(f, "no node matches the start nodes"),
+            WalkError::Unreachable(name) => write!(f, "no goal node is reachable from {}", name),
+        }
+    }
+}
+
+fn check_network(network: &Network) -> Result<(), WalkError> {
+    for node in network.nodes.values() {
+        if let Some(child) = node
+            .children
+            .iter()
+            .find(|c| !network.nodes.contains_key(*c))
+        {
+            return Err(WalkError::UnknownNode(child.clone()));
         }
-        num_steps += 1;
     }
-    num_steps
+    Ok(())
+}
+
+fn node_transition<'a>(network: &'a Network, from: &str, direction: Direction) -> &'a str {
+    &network.nodes[from].children[dir_to_index(&direction)]
 }
 
-fn find_cycle(network: &Network, start_nodename: NodeName) -> u64 {
-    let mut cycle_states: HashMap<(usize, NodeName), u64> = HashMap::new();
-    let mut traversed_z_steps: Vec<u64> = Vec::new();
-    let mut cur_node_name = start_nodename;
-    let mut num_steps = 0_u64;
-    let mut cycle_start_step: Option<u64> = None;
+// Whether any goal node can be reached from start when ignoring the instructions.
+fn can_reach(network: &Network, start: &str, goals: &NodeSet) -> bool {
+    let mut visited: HashSet<&str> = HashSet::from([start]);
+    let mut to_visit: VecDeque<&str> = VecDeque::from([start]);
 
-    for (inst_id, d) in network.instructions.iter().enumerate().cycle() {
-        if cur_node_name[2] == 'Z' {
-            traversed_z_steps.push(num_steps);
+    while let Some(name) = to_visit.pop_front() {
+        if goals.contains(name) {
+            return true;
         }
-        let cur_state = (inst_id, cur_node_name);
-        cycle_start_step = cycle_states.get(&cur_state).copied();
-        match cycle_start_step {
-            Some(_) => break,
-            _ => (),
+        for child in network.nodes[name].children.iter() {
+            if visited.insert(child) {
+                to_visit.push_back(child);
+            }
         }
-        cycle_states.insert(cur_state, num_steps);
+    }
+    false
+}
 
+fn start_nodes<'a>(network: &'a Network, starts: &NodeSet) -> Vec<&'a str> {
+    network
+        .nodes
+        .keys()
+        .filter(|name| starts.contains(name))
+        .map(|name| name.as_str())
+        .sorted()
+        .collect()
+}
+
+// The walk is over (instruction index, node) states, so once a state repeats without a goal
+// node having been visited none ever will be.
+fn follow_until(network: &Network, start: &str, goals: &NodeSet) -> Result<u64, WalkError> {
+    if !network.nodes.contains_key(start) {
+        return Err(WalkError::UnknownNode(start.to_string()));
+    }
+    if !can_reach(network, start, goals) {
+        return Err(WalkError::Unreachable(start.to_string()));
+    }
+    let mut visited: HashSet<(usize, &str)> = HashSet::new();
+    let mut cur_node_name = start;
+
+    for (num_steps, (inst_id, d)) in network.instructions.iter().enumerate().cycle().enumerate() {
+        if goals.contains(cur_node_name) {
+            return Ok(num_steps as u64);
+        }
+        if !visited.insert((inst_id, cur_node_name)) {
+            break;
+        }
         cur_node_name = node_transition(network, cur_node_name, *d);
-        num_steps += 1;
-    }
-    // Here we have detected a cycle.
-    let cycle_start_step = cycle_start_step.unwrap();
-    let cycle_end_step = num_steps;
-    let z_steps_in_cycle = Vec::from_iter(traversed_z_steps.iter().filter_map(|t| {
-        if cycle_start_step < *t && *t <= cycle_end_step {
-            Some(*t)
+    }
+    Err(WalkError::Unreachable(start.to_string()))
+}
+
+// Visits of a single ghost to goal nodes. The walk over (instruction index, node) states
+// eventually loops, so goal steps before the loop are only ever visited once while those within
+// the first loop iteration repeat forever.
+#[derive(Debug, Clone)]
+struct GhostCycle {
+    cycle: Cycle,
+    prefix_goal_steps: Vec<u64>,
+    cycle_goal_steps: Vec<u64>,
+}
+
+impl GhostCycle {
+    pub fn is_goal_step(&self, step: u64) -> bool {
+        if step < self.cycle.prefix_len {
+            self.prefix_goal_steps.contains(&step)
         } else {
-            None
+            self.cycle_goal_steps.contains(&self.cycle.reduce(step))
         }
-    }));
-    // start by skipping to the first z step in the cycle to make calculations easier
-    let cycle_start_to_first_z = z_steps_in_cycle[0] - cycle_start_step;
-    num_steps += cycle_start_to_first_z;
+    }
+}
+
+fn find_cycle(network: &Network, start_nodename: &str, goals: &NodeSet) -> GhostCycle {
+    let num_instructions = network.instructions.len();
+    // States are (instruction index, node) pairs.
+    let history = cycle::hashing((0, start_nodename), |(inst_id, node_name)| {
+        let direction = network.instructions[*inst_id];
+        (
+            (inst_id + 1) % num_instructions,
+            node_transition(network, node_name, direction),
+        )
+    });
+    let prefix_len = history.cycle.prefix_len;
+    let (cycle_goal_steps, prefix_goal_steps) = history
+        .states
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, node_name))| goals.contains(node_name))
+        .map(|(step, _)| step as u64)
+        .partition(|t| *t >= prefix_len);
 
-    // Then compute the time steps between successive z steps.
-    let mut z_deltas: Vec<u64> = Vec::new();
+    GhostCycle {
+        cycle: history.cycle,
+        prefix_goal_steps,
+        cycle_goal_steps,
+    }
+}
 
-    for i in 1..z_steps_in_cycle.len() {
-        z_deltas.push(z_steps_in_cycle[i] - z_steps_in_cycle[i - 1]);
+// Solves t = r1 mod m1 and t = r2 mod m2 for moduli that are not necessarily coprime,
+// returning the combined (residue, modulus) or None when the system is inconsistent.
+fn crt_pair((r1, m1): (i128, i128), (r2, m2): (i128, i128)) -> Option<(i128, i128)> {
+    let ExtendedGcd { gcd, x, .. } = m1.extended_gcd(&m2);
+    if (r2 - r1) % gcd != 0 {
+        return None;
     }
-    let last_z_to_cycle_end = cycle_end_step - z_steps_in_cycle.last().unwrap();
-    z_deltas.push(last_z_to_cycle_end + cycle_start_to_first_z);
+    let modulus = m1 / gcd * m2;
+    let k = ((r2 - r1) / gcd * x).rem_euclid(m2 / gcd);
+    Some(((r1 + m1 * k).rem_euclid(modulus), modulus))
+}
+
+fn first_common_goal_step(cycles: &[GhostCycle]) -> Option<u64> {
+    let max_prefix = cycles.iter().map(|c| c.cycle.prefix_len).max()?;
 
-    assert!(z_deltas.len() == 1);
-    assert!(traversed_z_steps.len() == 1);
-    assert!(z_deltas[0] == traversed_z_steps[0]);
+    // Before every ghost has entered its loop, steps are checked one by one against the goal
+    // steps of the first ghost.
+    let first = &cycles[0];
+    let early_candidates = first.prefix_goal_steps.iter().copied().chain(
+        first
+            .cycle_goal_steps
+            .iter()
+            .flat_map(|z| (*z..max_prefix).step_by(first.cycle.period as usize)),
+    );
+    if let Some(step) = early_candidates
+        .filter(|t| *t < max_prefix && cycles.iter().all(|c| c.is_goal_step(*t)))
+        .min()
+    {
+        return Some(step);
+    }
 
-    z_deltas[0]
+    // Afterwards all ghosts are periodic, so every combination of goal offsets gives a system of
+    // congruences. Each ghost's residues are merged into the solutions found so far.
+    let mut solutions: Vec<(i128, i128)> = Vec::from([(0, 1)]);
+    for ghost in cycles {
+        let period = ghost.cycle.period as i128;
+        let residues = ghost
+            .cycle_goal_steps
+            .iter()
+            .map(|z| ((*z as i128).rem_euclid(period), period));
+        solutions = solutions
+            .iter()
+            .cartesian_product(residues)
+            .filter_map(|(sol, res)| crt_pair(*sol, res))
+            .unique()
+            .collect();
+        if solutions.is_empty() {
+            return None;
+        }
+    }
+    let max_prefix = max_prefix as i128;
+    solutions
+        .into_iter()
+        .map(|(residue, modulus)| {
+            let num_periods = (max_prefix - residue + modulus - 1)
+                .div_euclid(modulus)
+                .max(0);
+            (residue + num_periods * modulus) as u64
+        })
+        .min()
 }
 
-fn follow_parallel(network: &Network) -> u64 {
-    network
-        .nodes
-        .keys()
-        .filter(|name| name[2] == 'A')
-        .map(|name| find_cycle(network, *name))
-        .fold(1, |lcm_acc, cycle_length| lcm(lcm_acc, cycle_length))
+fn follow_parallel(
+    network: &Network,
+    starts: &NodeSet,
+    goals: &NodeSet,
+) -> Result<Option<u64>, WalkError> {
+    let start_names = start_nodes(network, starts);
+    if start_names.is_empty() {
+        return Err(WalkError::NoStartNode);
+    }
+    if let Some(name) = start_names.iter().find(|n| !can_reach(network, n, goals)) {
+        return Err(WalkError::Unreachable(name.to_string()));
+    }
+    let cycles = Vec::from_iter(
+        start_names
+            .iter()
+            .map(|name| find_cycle(network, name, goals)),
+    );
+    // Goal nodes next to the path may still never be visited when following the instructions.
+    if let Some((name, _)) = start_names
+        .iter()
+        .zip(&cycles)
+        .find(|(_, c)| c.prefix_goal_steps.is_empty() && c.cycle_goal_steps.is_empty())
+    {
+        return Err(WalkError::Unreachable(name.to_string()));
+    }
+    Ok(first_common_goal_step(&cycles))
 }
 
 fn main() {
     let src = std::fs::read_to_string(std::env::args().nth(1).unwrap()).unwrap();
     let network = parser().parse(src.clone()).unwrap();
+    check_network(&network).unwrap();
 
-    println!(
-        "Question 1 answer is: {}",
-        follow_until(&network, ['A', 'A', 'A'], ['Z', 'Z', 'Z'])
-    );
-    println!("Question 2 answer is: {}", follow_parallel(&network))
+    // "analyze [starts [goals [dot file]]]" prints a report on the network instead of the
+    // answers.
+    if std::env::args().nth(2).as_deref() == Some("analyze") {
+        let starts = NodeSet::from_spec(&std::env::args().nth(3).unwrap_or("re:A$".to_string()));
+        let goals = NodeSet::from_spec(&std::env::args().nth(4).unwrap_or("re:Z$".to_string()));
+        let dot_path = std::env::args().nth(5);
+        analysis::analyze(
+            &network,
+            &starts.unwrap(),
+            &goals.unwrap(),
+            dot_path.as_deref(),
+        );
+        return;
+    }
+
+    let goal = NodeSet::from_spec("ZZZ").unwrap();
+    match follow_until(&network, "AAA", &goal) {
+        Ok(num_steps) => println!("Question 1 answer is: {}", num_steps),
+        Err(err) => println!("Question 1 has no answer: {}", err),
+    }
+    // Starts and goals default to the puzzle's ghosts but can be given as extra arguments.
+    let starts = NodeSet::from_spec(&std::env::args().nth(2).unwrap_or("re:A$".to_string()));
+    let goals = NodeSet::from_spec(&std::env::args().nth(3).unwrap_or("re:Z$".to_string()));
+    match follow_parallel(&network, &starts.unwrap(), &goals.unwrap()) {
+        Ok(Some(num_steps)) => println!("Question 2 answer is: {}", num_steps),
+        Ok(None) => println!("Question 2 has no answer, the ghosts never reach goals together"),
+        Err(err) => println!("Question 2 has no answer: {}", err),
+    }
 }
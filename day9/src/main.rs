@@ -1,13 +1,15 @@
 use chumsky::prelude::*;
+use itertools::Itertools;
+use num::{BigInt, Zero};
 
-type History = Vec<i32>;
+type History = Vec<BigInt>;
 type Oasis = Vec<History>;
 
 fn parser() -> impl Parser<char, Oasis, Error = Simple<char>> {
-    let number = just("-").repeated().at_most(1).then(text::int(10)).map(
-        |(opt_sign, int_str): (Vec<&str>, String)| {
-            let nat: i32 = int_str.parse().unwrap();
-            match opt_sign.get(0) {
+    let number = just("-").or_not().then(text::int(10)).map(
+        |(opt_sign, int_str): (Option<&str>, String)| {
+            let nat: BigInt = int_str.parse().unwrap();
+            match opt_sign {
                 None => nat,
                 Some(_) => -nat,
             }
@@ -15,52 +17,210 @@ fn parser() -> impl Parser<char, Oasis, Error = Simple<char>> {
     );
     let line = number.separated_by(just(" ").repeated().at_least(1));
     line.separated_by(text::newline())
+        .map(|lines| Vec::from_iter(lines.into_iter().filter(|h: &History| !h.is_empty())))
 }
 
-fn sequences(history: &History) -> Vec<History> {
-    if history.into_iter().all(|i| *i == 0) {
-        Vec::from([history.clone()])
+#[derive(Debug)]
+enum FitError {
+    Empty,
+    // The differences never reach an all zero row, so the degree is at least len - 1 and
+    // nothing can be said about values outside of the history.
+    NotPolynomial { len: usize },
+}
+
+impl std::fmt::Display for FitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FitError::Empty => write!(f, "history is empty"),
+            FitError::NotPolynomial { len } => write!(
+                f,
+                "history of length {} is not a polynomial of degree lower than {}",
+                len,
+                len.saturating_sub(1)
+            ),
+        }
+    }
+}
+
+// Difference pyramid of a history, from the all zero row up to the history itself.
+fn sequences(history: &[BigInt]) -> Result<Vec<History>, FitError> {
+    if history.is_empty() {
+        Err(FitError::Empty)
+    } else if history.iter().all(|i| i.is_zero()) {
+        Ok(Vec::from([history.to_vec()]))
+    } else if history.len() == 1 {
+        Err(FitError::NotPolynomial { len: 1 })
     } else {
-        let deltas = Vec::from_iter((1..history.len()).map(|i| history[i] - history[i - 1]));
-        let mut rest = sequences(&deltas);
+        let deltas = Vec::from_iter(history.windows(2).map(|w| &w[1] - &w[0]));
+        let mut rest = sequences(&deltas).map_err(|err| match err {
+            FitError::NotPolynomial { len } => FitError::NotPolynomial { len: len + 1 },
+            err => err,
+        })?;
         rest.push(history.to_vec());
-        rest
+        Ok(rest)
+    }
+}
+
+// Polynomial in Newton form: the forward differences Δ^k f(0) for k up to the degree.
+#[derive(Debug, Clone)]
+struct Polynomial {
+    forward_differences: Vec<BigInt>,
+}
+
+impl Polynomial {
+    pub fn fit(history: &[BigInt]) -> Result<Polynomial, FitError> {
+        let pyramid = sequences(history)?;
+        Ok(Polynomial {
+            forward_differences: Vec::from_iter(pyramid[1..].iter().rev().map(|s| s[0].clone())),
+        })
+    }
+
+    // The zero polynomial is reported with degree 0 like any other constant.
+    pub fn degree(&self) -> usize {
+        self.forward_differences.len().saturating_sub(1)
+    }
+
+    // f(x) = sum_k binomial(x, k) Δ^k f(0), where the binomial coefficient is extended to
+    // negative x and stays an integer at each step of the product.
+    pub fn value_at(&self, x: i64) -> BigInt {
+        let x = BigInt::from(x);
+        let mut binomial = BigInt::from(1);
+        let mut value = BigInt::zero();
+
+        for (k, delta) in self.forward_differences.iter().enumerate() {
+            if k > 0 {
+                binomial = binomial * (&x - (k - 1)) / k;
+            }
+            value += &binomial * delta;
+        }
+        value
+    }
+}
+
+// Value `steps` positions after the last element of the history, or before the first one for
+// negative steps.
+fn extrapolate(history: &[BigInt], steps: i64) -> Result<BigInt, FitError> {
+    let polynomial = Polynomial::fit(history)?;
+    let x = if steps >= 0 {
+        history.len() as i64 - 1 + steps
+    } else {
+        steps
+    };
+    Ok(polynomial.value_at(x))
+}
+
+// Difference pyramid of the history extended by one extrapolated value on each end.
+fn extended_pyramid(history: &[BigInt]) -> Result<Vec<History>, FitError> {
+    let polynomial = Polynomial::fit(history)?;
+    let mut extended = Vec::from([polynomial.value_at(-1)]);
+    extended.extend(history.iter().cloned());
+    extended.push(polynomial.value_at(history.len() as i64));
+    let mut pyramid = sequences(&extended)?;
+    pyramid.reverse();
+    Ok(pyramid)
+}
+
+// Draws the pyramid as in the puzzle statement, each row shifted by half a cell so that
+// differences sit between the values they come from. Extrapolated ends are highlighted.
+fn render_pyramid(history: &[BigInt]) -> String {
+    let pyramid = match extended_pyramid(history) {
+        Ok(pyramid) => pyramid,
+        Err(err) => {
+            let values = history.iter().map(|v| v.to_string()).join(" ");
+            return format!("{}\n  cannot extrapolate: {}\n", values, err);
+        }
+    };
+    let max_len = pyramid
+        .iter()
+        .flatten()
+        .map(|v| v.to_string().len())
+        .max()
+        .unwrap();
+    let cell_width = (max_len + 2).next_multiple_of(2);
+    let mut rendered = String::new();
+
+    for (depth, row) in pyramid.iter().enumerate() {
+        rendered.push_str(&" ".repeat(depth * cell_width / 2));
+        for (i, value) in row.iter().enumerate() {
+            let cell = format!("{:>width$}", value, width = cell_width);
+            if i == 0 || i == row.len() - 1 {
+                rendered.push_str(&format!("\x1b[1;32m{}\x1b[0m", cell));
+            } else {
+                rendered.push_str(&cell);
+            }
+        }
+        rendered.push('\n');
     }
+    rendered
 }
 
-fn extrapolate(history: &History, forward: bool) -> Vec<i32> {
-    let deltas = sequences(history);
-    let mut extrapolated = Vec::from([0]);
-
-    for i in 1..deltas.len() {
-        let below = *extrapolated.last().unwrap();
-        if forward {
-            let above = deltas[i].last().unwrap();
-            extrapolated.push(above + below);
-        } else {
-            let above = deltas[i].first().unwrap();
-            extrapolated.push(above - below);
+fn json_list(values: &[BigInt]) -> String {
+    format!("[{}]", values.iter().map(|v| v.to_string()).join(", "))
+}
+
+fn pyramid_json(history: &[BigInt]) -> String {
+    match extended_pyramid(history) {
+        Ok(pyramid) => {
+            let rows = Vec::from_iter(pyramid.iter().map(|row| json_list(&row[1..row.len() - 1])));
+            let backward = Vec::from_iter(pyramid.iter().map(|row| row[0].clone()));
+            let forward = Vec::from_iter(pyramid.iter().map(|row| row[row.len() - 1].clone()));
+            format!(
+                "{{\"history\": {}, \"pyramid\": [{}], \"backward\": {}, \"forward\": {}}}",
+                json_list(history),
+                rows.join(", "),
+                json_list(&backward),
+                json_list(&forward)
+            )
         }
+        Err(err) => format!(
+            "{{\"history\": {}, \"error\": \"{}\"}}",
+            json_list(history),
+            err
+        ),
     }
-    extrapolated
+}
+
+fn sum_extrapolated(oasis: &Oasis, steps: i64) -> Result<BigInt, FitError> {
+    oasis.iter().map(|h| extrapolate(h, steps)).sum()
 }
 
 fn main() {
     let src = std::fs::read_to_string(std::env::args().nth(1).unwrap()).unwrap();
     let oasis = parser().parse(src.clone()).unwrap();
 
-    println!(
-        "Question 1 answer is {}",
-        oasis
-            .iter()
-            .map(|h| extrapolate(&h, true).last().unwrap().clone())
-            .sum::<i32>()
-    );
-    println!(
-        "Question 2 answer is {}",
-        oasis
+    // "pyramid" draws the difference pyramid of every history, "pyramid-json" dumps them.
+    match std::env::args().nth(2).as_deref() {
+        Some("pyramid") => {
+            oasis.iter().for_each(|h| println!("{}", render_pyramid(h)));
+            return;
+        }
+        Some("pyramid-json") => {
+            println!("[{}]", oasis.iter().map(|h| pyramid_json(h)).join(",\n "));
+            return;
+        }
+        _ => (),
+    }
+
+    match sum_extrapolated(&oasis, 1) {
+        Ok(sum) => println!("Question 1 answer is {}", sum),
+        Err(err) => println!("Question 1 has no answer: {}", err),
+    }
+    match sum_extrapolated(&oasis, -1) {
+        Ok(sum) => println!("Question 2 answer is {}", sum),
+        Err(err) => println!("Question 2 has no answer: {}", err),
+    }
+    // An optional horizon extrapolates further, forward or backward depending on its sign.
+    if let Some(steps) = std::env::args().nth(2) {
+        let steps: i64 = steps.parse().unwrap();
+        let max_degree = oasis
             .iter()
-            .map(|h| extrapolate(&h, false).last().unwrap().clone())
-            .sum::<i32>()
-    )
+            .filter_map(|h| Polynomial::fit(h).ok())
+            .map(|p| p.degree())
+            .max();
+        println!("Highest history degree is {:?}", max_degree);
+        match sum_extrapolated(&oasis, steps) {
+            Ok(sum) => println!("Sum extrapolated {} steps away is {}", steps, sum),
+            Err(err) => println!("Cannot extrapolate {} steps away: {}", steps, err),
+        }
+    }
 }